@@ -4,7 +4,9 @@ use crate::{Args, BuildService};
 use apps::Getter;
 use base::time::now;
 use base::trace::Alive;
-use block_builder::{BlockStateFetcher, Simulator};
+use block_builder::{
+    CallBundleRequest, CallBundleResult, Simulator, TraceCallRequest, TraceConfig, TraceResult,
+};
 use eth_client::{BeaconSlot, ExecutionClient, HeadState};
 use eth_types::{HexBytes, SH256, SU64};
 use eth_types::{PoolTx, Signer, TransactionInner};
@@ -76,7 +78,7 @@ impl PublicApi {
         let tx = PoolTx::with_tx(&self.signer, tx);
         let hash = tx.hash;
         let head = self.head_state.get();
-        let fetcher = BlockStateFetcher::new(self.el.clone(), head.number.into());
+        let fetcher = self.simulator.fetcher(&head);
         let state = TrieState::new(fetcher, head.clone(), self.store.fork());
         let result = self
             .simulator
@@ -92,6 +94,51 @@ impl PublicApi {
         Ok(hash)
     }
 
+    pub fn trace_call(
+        &self,
+        args: RpcArgs<(TraceCallRequest, String, TraceConfig)>,
+    ) -> Result<TraceResult, JsonrpcErrorObj> {
+        let (req, blk, cfg) = &args.params;
+        if blk != "latest" {
+            return Err(JsonrpcErrorObj::client(format!(
+                "unsupported block: {}, only latest is available",
+                blk
+            )));
+        }
+        let head = self.head_state.get();
+        let fetcher = self.simulator.fetcher(&head);
+        let state = TrieState::new(fetcher, head.clone(), self.store.fork());
+        self.simulator
+            .trace_call(state, &head, req, cfg)
+            .map_err(|err| JsonrpcErrorObj::client(format!("trace fail: {:?}", err)))
+    }
+
+    pub fn call_bundle(
+        &self,
+        args: RpcArgs<(CallBundleRequest,)>,
+    ) -> Result<CallBundleResult, JsonrpcErrorObj> {
+        let req = &args.params.0;
+        if req.state_block_number != "latest" {
+            return Err(JsonrpcErrorObj::client(format!(
+                "unsupported state block: {}, only latest is available",
+                req.state_block_number
+            )));
+        }
+        let mut txs = Vec::with_capacity(req.txs.len());
+        for tx in &req.txs {
+            let tx = TransactionInner::from_bytes(tx)
+                .map_err(|err| JsonrpcErrorObj::client(format!("invalid tx payload: {:?}", err)))?;
+            txs.push(PoolTx::with_tx(&self.signer, tx));
+        }
+        let head = self.head_state.get();
+        let header = req.header(&head, self.simulator.spec());
+        let fetcher = self.simulator.fetcher(&head);
+        let state = TrieState::new(fetcher, head.clone(), self.store.fork());
+        self.simulator
+            .simulate_bundle(state, &header, &txs)
+            .map_err(|err| JsonrpcErrorObj::client(format!("simulate fail: {:?}", err)))
+    }
+
     pub fn chain_id(&self, _: RpcArgs) -> Result<SU64, JsonrpcErrorObj> {
         Ok(self.signer.chain_id.as_u64().into())
     }
@@ -140,6 +187,16 @@ impl PublicApi {
             .unwrap();
         HttpResponseBuilder::new(200).json(result).into()
     }
+
+    pub fn get_build_trace(&self, _: HttpRequestReader) -> HttpResponse {
+        let result = serde_json::to_vec(&self.build_service.traces()).unwrap();
+        HttpResponseBuilder::new(200).json(result).into()
+    }
+
+    pub fn get_predictor_stat(&self, _: HttpRequestReader) -> HttpResponse {
+        let result = serde_json::to_vec(&self.build_service.predictor_stat()).unwrap();
+        HttpResponseBuilder::new(200).json(result).into()
+    }
 }
 
 impl Getter<PublicApi> for App {
@@ -185,9 +242,13 @@ impl Getter<RpcServer<PublicApi>> for App {
         srv.jsonrpc("eth_chainId", PublicApi::chain_id);
         // srv.jsonrpc("eth_sendBundle", PublicApi::send_bundle);
         srv.jsonrpc("eth_sendRawTransaction", PublicApi::send_raw_transaction);
+        srv.jsonrpc("debug_traceCall", PublicApi::trace_call);
+        srv.jsonrpc("eth_callBundle", PublicApi::call_bundle);
         srv.http_get("/test", PublicApi::test);
         srv.http_get("/", PublicApi::index);
         srv.http_get("/bundles", PublicApi::get_bundle_list);
+        srv.http_get("/trace", PublicApi::get_build_trace);
+        srv.http_get("/predictor", PublicApi::get_predictor_stat);
         srv.default_jsonrpc(PublicApi::default);
         srv
     }
@@ -53,6 +53,10 @@ impl apps::App for App {
 
         glog::info!("{:?} {:?}", self.args, self.cfg);
 
+        if let Some(path) = &self.args.get(self).replay {
+            return self.replay(path);
+        }
+
         base::thread::spawn("collect-tx".into(), {
             let tx_fetcher = self.tx_fetcher.get(self);
             let _alive = self.alive.clone();
@@ -62,14 +66,14 @@ impl apps::App for App {
             let secs = Duration::from_secs(1);
             move || loop {
                 tx_fetcher.recv_iter(secs, |item| match item {
-                    MempoolItem::Bundle(bundle) => {
+                    MempoolItem::Bundle(bundle, dropping) => {
                         let head = head_state.get();
                         let next = beacon_slot.next_block_time(
                             &head,
                             bundle.block_number.as_u64() - head.number.as_u64(),
                         );
                         let dur = Time::from(next).duration_since(Time::now());
-                        let _ = txpool.bundle_pool.add(bundle, &dur);
+                        let _ = txpool.bundle_pool.add(bundle, dropping, &dur);
                     }
                     MempoolItem::Price(tx) => {
                         let _ = txpool.price_pool.push(tx);
@@ -115,42 +119,45 @@ impl apps::App for App {
 }
 
 impl App {
+    pub fn replay(&self, path: &str) -> Result<(), String> {
+        let record = block_builder::BuildRecord::load(path)?;
+        let store = TrieMemStore::new(self.cfg.get(self).trie_store_size);
+        let result = self
+            .builder
+            .get(self)
+            .replay(record, store)
+            .map_err(|err| format!("replay fail: {:?}", err))?;
+        glog::info!(
+            "replay result: block {:?}, txs: {}, trace: {}",
+            result.block.header.hash(),
+            result.block.transactions.len(),
+            serde_json::to_string(&result.trace).unwrap(),
+        );
+        Ok(())
+    }
+
+    // re-executes the block locally and logs where it differs from the header
     pub fn compare_block(
-        cl: &eth_client::ExecutionClient,
+        &self,
         block: &eth_types::Block,
         receipts: Option<&Vec<eth_types::Receipt>>,
     ) {
-        use eth_client::BuildPayloadArgs;
-        use eth_types::Block;
-        let block = block.clone();
-        let tx_list_bytes = block
-            .transactions
-            .iter()
-            .map(|tx| eth_types::HexBytes::from(tx.clone().inner().unwrap().to_bytes()))
-            .collect::<Vec<_>>();
-        let args = BuildPayloadArgs {
-            parent: block.header.parent_hash,
-            timestamp: block.header.timestamp.as_u64(),
-            feeRecipient: block.header.miner,
-            random: block.header.mix_hash,
-            withdrawals: block.withdrawals.clone(),
-            txsBytes: tx_list_bytes,
-        };
-
-        let new_block = cl.seal_block(&args).unwrap();
-        glog::info!("block diff: ================================================");
-        if let Err(err) = Block::compare(&new_block, &block) {
-            glog::info!("blk: {:?}", new_block.header);
-            // glog::info!("expect blk: {:?} , submit tx: {}", expect_blk, test_txs_len);
-            if let Some(receipts) = receipts {
-                if block.header.receipts_root != new_block.header.receipts_root {
-                    for receipt in receipts {
-                        glog::info!("receipts: {:?}", receipt);
-                    }
-                }
+        let store = TrieMemStore::new(self.cfg.get(self).trie_store_size);
+        let result = self.builder.get(self).validate_block(
+            store,
+            block,
+            receipts.map(|receipts| receipts.as_slice()),
+        );
+        match result {
+            Ok(result) if result.is_valid() => {
+                glog::info!("block #{} {:?} is valid", result.number, result.hash)
             }
-            println!("=============================================================================\n\n{}", err);
+            Ok(result) => glog::error!("block diff: {}", serde_json::to_string(&result).unwrap()),
+            Err(err) => glog::error!(
+                "validate block #{} fail: {:?}",
+                block.header.number.as_u64(),
+                err
+            ),
         }
-        glog::info!("block diff end: ============================================");
     }
 }
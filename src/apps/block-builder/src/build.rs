@@ -4,9 +4,10 @@ use super::App;
 use apps::Getter;
 use base::time::{Time, Date};
 use base::trace::Alive;
-use block_builder::{BuildError, BuildPayload};
+use block_builder::{BuildError, BuildPayload, BuildTrace, PredictStat};
 use eth_client::{BeaconHead, BlockReport};
 use eth_client::{BeaconHeadState, BeaconSlot, ExecutionClient, HashPool, HeadState};
+use mev_boost::{RelayGroup, ValidatorData};
 use statedb::TrieMemStore;
 use statedb::TrieStore;
 use std::collections::BTreeMap;
@@ -25,6 +26,7 @@ pub struct BuildService {
     txpool: Arc<TxPool>,
     submit_time: Duration,
     current_alive: Mutex<Option<Alive>>,
+    traces: Mutex<BTreeMap<(u64, usize), BuildTrace>>,
 }
 
 impl BuildService {
@@ -42,6 +44,23 @@ impl BuildService {
         current_alive.as_ref().map(|alive| alive.shutdown());
     }
 
+    pub fn traces(&self) -> Vec<BuildTrace> {
+        let traces = self.traces.lock().unwrap();
+        traces.values().cloned().collect()
+    }
+
+    pub fn predictor_stat(&self) -> Option<PredictStat> {
+        self.builder.predictor_stat()
+    }
+
+    fn save_trace(&self, trace: BuildTrace) {
+        let mut traces = self.traces.lock().unwrap();
+        traces.insert((trace.slot, trace.round), trace);
+        while traces.len() > 32 {
+            traces.pop_first();
+        }
+    }
+
     fn build_multiple_rounds(&self, mut head: BeaconHead) {
         let mut round = 0;
         while self.alive.is_alive() {
@@ -78,7 +97,35 @@ impl BuildService {
         *self.current_alive.lock().unwrap() = Some(alive.clone());
 
         glog::info!("slot:{}, vd: {:?}", head.slot + 1, vd);
-        let payload = BuildPayload {
+        // the bid is paid inside the block, so the relays using different bid strategies
+        // need different blocks.
+        for group in self.relay.relay_groups(&vd) {
+            if !alive.is_alive() {
+                break;
+            }
+            self.build_for_relays(&alive, round, head, &vd, group, deadline);
+        }
+        return true;
+    }
+
+    fn build_for_relays(
+        &self,
+        alive: &Alive,
+        round: usize,
+        head: &BeaconHead,
+        vd: &ValidatorData,
+        group: RelayGroup,
+        deadline: Time,
+    ) {
+        let new_slot = head.slot + 1;
+        let top_bid = match &group.bid_strategy {
+            Some(strategy) if strategy.need_top_bid() => {
+                self.relay
+                    .get_top_bid(new_slot, head.block.hash(), vd, &group.relays)
+            }
+            _ => None,
+        };
+        let payload = Arc::new(BuildPayload {
             round,
             slot: new_slot,
             base: head.block.clone(),
@@ -89,24 +136,30 @@ impl BuildService {
             extra: self.builder.cfg.extra.clone().into(),
             withdrawals: head.withdrawal(),
             tips_recipient: Some(vd.fee_recipient),
-        };
+            bid_strategy: group.bid_strategy,
+            top_bid,
+        });
         match self
             .builder
-            .build(&alive, self.store.fork(), &self.txpool, &payload)
+            .clone()
+            .build_parallel(alive, &self.store, &self.txpool, &payload)
         {
             Ok(blk) => {
+                self.save_trace(blk.trace.clone());
                 let deadline = deadline + self.submit_time;
                 let now = Time::now();
                 let available_for_submit =
                     deadline > now && deadline - now <= Duration::from_secs(3);
                 glog::info!(
-                    "remain_time(deadline): {:?}, available_for_submit: {}",
+                    "remain_time(deadline): {:?}, available_for_submit: {}, strategy: {:?}, {:?}",
                     deadline.duration_since(now),
-                    available_for_submit
+                    available_for_submit,
+                    blk.strategy,
+                    group.relays,
                 );
                 if available_for_submit {
                     self.relay
-                        .submit_block(blk.slot, &vd, &blk.block, blk.profit);
+                        .submit_block(blk.slot, vd, &group.relays, &blk.block, blk.profit);
                     for bundle in blk.bundles {
                         self.txpool
                             .bundle_pool
@@ -121,7 +174,6 @@ impl BuildService {
                 }
             }
         };
-        return true;
     }
 }
 
@@ -138,6 +190,7 @@ impl Getter<BuildService> for App {
             txpool: self.txpool.get(self),
             submit_time: Duration::from_millis(cfg.mev_boost_relay.submit_time_millis),
             current_alive: Mutex::new(None),
+            traces: Mutex::new(BTreeMap::new()),
         }
     }
 }
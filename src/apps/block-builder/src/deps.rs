@@ -108,6 +108,7 @@ impl Getter<Simulator> for App {
             cfg.simulator_thread,
             self.el.get(self),
         )
+        .with_verify(cfg.builder.verify_state)
     }
 }
 
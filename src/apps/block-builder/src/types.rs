@@ -43,6 +43,8 @@ pub struct Args {
     pub executable: String,
     pub port: u32,
     pub cfg: String,
+    // replays the build record instead of running the service
+    pub replay: Option<String>,
 
     pub enclave_id: u64,
 }
@@ -53,6 +55,7 @@ impl Default for Args {
             executable: "".into(),
             port: 18231,
             cfg: "config.json".into(),
+            replay: None,
             enclave_id: 0,
         }
     }
@@ -71,6 +74,9 @@ impl Args {
                 Opt::Short('c') => {
                     out.cfg = opts.value().unwrap().parse().unwrap();
                 }
+                Opt::Short('r') => {
+                    out.replay = Some(opts.value().unwrap().parse().unwrap());
+                }
                 _ => continue,
             }
         }
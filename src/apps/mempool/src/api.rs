@@ -1,17 +1,14 @@
 use std::prelude::v1::*;
 
-use crate::{App, PendingTransactionInfo, StatBundle};
+use crate::{App, PendingTransactionInfo, StatBundle, SubscriptionItem};
 use apps::Getter;
 use base::time::Time;
-use block_builder::Simulator;
+use block_builder::{CallBundleRequest, CallBundleResult, Simulator};
 use crypto::Secp256r1PublicKey;
 use eth_client::{BeaconSlot, ExecutionClient, HeadState};
-use eth_types::{
-    BundleRlp, HexBytes, PoolItem, PoolTx, PoolTxRlp, Signer, TransactionInner, SH256,
-};
-use evm_executor::BlockStateFetcher;
+use eth_types::{HexBytes, PoolTx, PoolTxRlp, Signer, TransactionInner, SH256};
 use jsonrpc::{JsonrpcErrorObj, RpcArgs, RpcError, RpcServer, RpcServerApi, RpcServerSubscription};
-use mempool::{GetBundleRequest, GetTxRequest, SubscribeOpt};
+use mempool::{BundleItem, GetBundleRequest, GetTxRequest, SubscribeOpt};
 use net_http::{HttpRequestReader, HttpResponse, HttpResponseBuilder};
 use serde_json::BoxRawValue;
 use statedb::TrieStore;
@@ -34,7 +31,7 @@ pub struct MempoolApi {
     store: Arc<TrieMemStore>,
     simulator: Arc<Simulator>,
 
-    subscribe_senders: Arc<Mutex<Option<mpsc::SyncSender<PoolItem>>>>,
+    subscribe_senders: Arc<Mutex<Option<mpsc::SyncSender<SubscriptionItem>>>>,
     subscriptions: Arc<Mutex<BTreeMap<String, (Secp256r1PublicKey, SubscribeOpt)>>>,
 
     #[cfg(feature = "sgx")]
@@ -44,14 +41,15 @@ pub struct MempoolApi {
 #[cfg(feature = "sgx")]
 sgxlib_ra::impl_jsonrpc_encrypt!(MempoolApi, ra_ctx);
 
-impl RpcServerApi<PoolItem> for MempoolApi {
-    fn init_api(self: &Arc<Self>, srv: &mut RpcServer<Self, PoolItem>) {
+impl RpcServerApi<SubscriptionItem> for MempoolApi {
+    fn init_api(self: &Arc<Self>, srv: &mut RpcServer<Self, SubscriptionItem>) {
         srv.http_get("/", Self::index);
         srv.http_get("/pending", Self::tx_stat);
         srv.http_get("/bundles", Self::bundle_stat);
 
         srv.jsonrpc("eth_sendRawTransaction", Self::send_raw_transaction);
         srv.jsonrpc("eth_sendBundle", Self::send_bundle);
+        srv.jsonrpc("eth_callBundle", Self::call_bundle);
 
         #[cfg(feature = "sgx")]
         {
@@ -126,7 +124,7 @@ impl MempoolApi {
     fn get_bundle(
         &self,
         args: RpcArgs<(GetBundleRequest,)>,
-    ) -> Result<Vec<BundleRlp>, JsonrpcErrorObj> {
+    ) -> Result<Vec<BundleItem>, JsonrpcErrorObj> {
         let (req,) = args.params;
 
         let block_number = req.block_number;
@@ -136,7 +134,13 @@ impl MempoolApi {
             .unwrap_or(base::time::now().as_secs());
 
         let list = self.txpool.bundle_pool.list(block_number, block_timestamp);
-        let list = list.iter().map(|item| item.to_rlp()).collect::<Vec<_>>();
+        let list = list
+            .iter()
+            .map(|item| BundleItem {
+                bundle: item.bundle.to_rlp(),
+                dropping_tx_hashes: item.dropping.iter().cloned().collect(),
+            })
+            .collect::<Vec<_>>();
         Ok(list)
     }
 
@@ -157,7 +161,7 @@ impl MempoolApi {
         let hash = tx.hash;
         let head = self.head_state.get();
 
-        let fetcher = BlockStateFetcher::new(self.el.clone(), head.number.into());
+        let fetcher = self.simulator.fetcher(&head);
         let state = TrieState::new(fetcher, head.clone(), self.store.fork());
         let mut result = self
             .simulator
@@ -173,7 +177,7 @@ impl MempoolApi {
                 .lock()
                 .unwrap()
                 .as_ref()
-                .map(|n| n.send(PoolItem::Tx(tx)));
+                .map(|n| n.send(SubscriptionItem::Tx(tx)));
         }
 
         Ok(hash)
@@ -185,6 +189,11 @@ impl MempoolApi {
             .0
             .to_bundle(&self.signer)
             .map_err(|err| JsonrpcErrorObj::client(format!("parse bundle fail: {}", err)))?;
+        let dropping = arg
+            .params
+            .0
+            .dropping_tx_hashes(&bundle)
+            .map_err(|err| JsonrpcErrorObj::client(format!("parse bundle fail: {}", err)))?;
         let hash = bundle.hash();
         let head = self.head_state.get();
 
@@ -212,16 +221,46 @@ impl MempoolApi {
             return Ok(hash);
         }
 
-        let _ = self.txpool.bundle_pool.add(bundle.clone(), &remain_time);
+        let _ = self
+            .txpool
+            .bundle_pool
+            .add(bundle.clone(), dropping.clone(), &remain_time);
         self.subscribe_senders
             .lock()
             .unwrap()
             .as_ref()
-            .map(|n| n.send(PoolItem::Bundle(bundle)));
+            .map(|n| n.send(SubscriptionItem::Bundle(bundle, dropping)));
 
         Ok(hash)
     }
 
+    // simulates the txs on top of the latest block and reports what they change
+    fn call_bundle(
+        &self,
+        args: RpcArgs<(CallBundleRequest,)>,
+    ) -> Result<CallBundleResult, JsonrpcErrorObj> {
+        let req = &args.params.0;
+        if req.state_block_number != "latest" {
+            return Err(JsonrpcErrorObj::client(format!(
+                "unsupported state block: {}, only latest is available",
+                req.state_block_number
+            )));
+        }
+        let mut txs = Vec::with_capacity(req.txs.len());
+        for tx in &req.txs {
+            let tx = TransactionInner::from_bytes(tx)
+                .map_err(|err| JsonrpcErrorObj::client(format!("invalid tx payload: {:?}", err)))?;
+            txs.push(PoolTx::with_tx(&self.signer, tx));
+        }
+        let head = self.head_state.get();
+        let header = req.header(&head, self.simulator.spec());
+        let fetcher = self.simulator.fetcher(&head);
+        let state = TrieState::new(fetcher, head.clone(), self.store.fork());
+        self.simulator
+            .simulate_bundle(state, &header, &txs)
+            .map_err(|err| JsonrpcErrorObj::client(format!("simulate fail: {:?}", err)))
+    }
+
     pub fn default(&self, args: RpcArgs<BoxRawValue>) -> Result<BoxRawValue, JsonrpcErrorObj> {
         if self.methods.iter().any(|item| item == args.method) {
             let response = match self.el.raw().rpc(args.method, &args.params) {
@@ -262,7 +301,7 @@ impl Getter<MempoolApi> for App {
     }
 }
 
-impl RpcServerSubscription<PoolItem> for MempoolApi {
+impl RpcServerSubscription<SubscriptionItem> for MempoolApi {
     fn methods(&self) -> (&'static str, &'static str, &'static str) {
         ("pool_subscribe", "pool_unsubscribe", "pool_subscription")
     }
@@ -270,7 +309,7 @@ impl RpcServerSubscription<PoolItem> for MempoolApi {
     #[cfg(feature = "sgx")]
     fn on_dispatch<'a>(
         &self,
-        new_item: &PoolItem,
+        new_item: &SubscriptionItem,
         ids: Vec<&'a str>,
     ) -> Vec<(BoxRawValue, Vec<&'a str>)> {
         use jsonrpc::RpcEncrypt;
@@ -279,8 +318,11 @@ impl RpcServerSubscription<PoolItem> for MempoolApi {
         let subscriptions = self.subscriptions.lock().unwrap();
 
         let val = match new_item {
-            PoolItem::Bundle(bundle) => serde_json::to_raw_value(&bundle.to_rlp()),
-            PoolItem::Tx(tx) => serde_json::to_raw_value(&tx.to_rlp()),
+            SubscriptionItem::Bundle(bundle, dropping) => serde_json::to_raw_value(&BundleItem {
+                bundle: bundle.to_rlp(),
+                dropping_tx_hashes: dropping.iter().cloned().collect(),
+            }),
+            SubscriptionItem::Tx(tx) => serde_json::to_raw_value(&tx.to_rlp()),
         }
         .unwrap();
 
@@ -292,8 +334,8 @@ impl RpcServerSubscription<PoolItem> for MempoolApi {
                 None => continue,
             };
             match (opt, new_item) {
-                (SubscribeOpt::NewBundle, PoolItem::Bundle(_)) => {}
-                (SubscribeOpt::NewTx, PoolItem::Tx(_)) => {}
+                (SubscribeOpt::NewBundle, SubscriptionItem::Bundle(..)) => {}
+                (SubscribeOpt::NewTx, SubscriptionItem::Tx(_)) => {}
                 _ => continue,
             };
 
@@ -313,7 +355,7 @@ impl RpcServerSubscription<PoolItem> for MempoolApi {
     #[cfg(not(feature = "sgx"))]
     fn on_dispatch<'a>(
         &self,
-        new_item: &PoolItem,
+        new_item: &SubscriptionItem,
         ids: Vec<&'a str>,
     ) -> Vec<(BoxRawValue, Vec<&'a str>)> {
         glog::info!("on dispatch: {:?} {:?}", new_item, ids);
@@ -322,8 +364,8 @@ impl RpcServerSubscription<PoolItem> for MempoolApi {
             let mut new_ids = Vec::with_capacity(ids.len());
             for id in ids {
                 match subscription.get(id).map(|(_pubkey, n)| (n, new_item)) {
-                    Some((SubscribeOpt::NewBundle, PoolItem::Bundle(_))) => {}
-                    Some((SubscribeOpt::NewTx, PoolItem::Tx(_))) => {}
+                    Some((SubscribeOpt::NewBundle, SubscriptionItem::Bundle(..))) => {}
+                    Some((SubscribeOpt::NewTx, SubscriptionItem::Tx(_))) => {}
                     _ => continue,
                 }
                 new_ids.push(id);
@@ -333,8 +375,13 @@ impl RpcServerSubscription<PoolItem> for MempoolApi {
 
         if new_ids.len() > 0 {
             let data = match new_item {
-                PoolItem::Bundle(bundle) => serde_json::to_raw_value(&bundle.to_rlp()),
-                PoolItem::Tx(tx) => serde_json::to_raw_value(&tx.to_rlp()),
+                SubscriptionItem::Bundle(bundle, dropping) => {
+                    serde_json::to_raw_value(&BundleItem {
+                        bundle: bundle.to_rlp(),
+                        dropping_tx_hashes: dropping.iter().cloned().collect(),
+                    })
+                }
+                SubscriptionItem::Tx(tx) => serde_json::to_raw_value(&tx.to_rlp()),
             }
             .unwrap();
             return vec![(data, new_ids)];
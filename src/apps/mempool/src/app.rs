@@ -6,12 +6,12 @@ use base::fs::parse_file;
 use base::trace::Alive;
 use block_builder::Simulator;
 use eth_client::{BeaconSlot, ExecutionClient, HashPool, HeadState, TxFetcher};
-use eth_types::{Signer, PoolItem};
+use eth_types::Signer;
 use jsonrpc::{MixRpcClient, RpcServer, RpcServerConfig};
 use statedb::TrieMemStore;
 use txpool::{TxPool};
 
-use crate::{Args, Config, MempoolApi};
+use crate::{Args, Config, MempoolApi, SubscriptionItem};
 
 #[derive(Default)]
 pub struct App {
@@ -23,7 +23,7 @@ pub struct App {
     pub _tx_fetcher: Var<TxFetcher>,
     pub hash_pool: Var<HashPool>,
     pub api: Var<MempoolApi>,
-    pub srv: VarMutex<RpcServer<MempoolApi, PoolItem>>,
+    pub srv: VarMutex<RpcServer<MempoolApi, SubscriptionItem>>,
     pub beacon_slot: Var<BeaconSlot>,
     pub el: Var<ExecutionClient>,
     pub txpool: Var<TxPool>,
@@ -99,8 +99,8 @@ impl Getter<HashPool> for App {
     }
 }
 
-impl Getter<RpcServer<MempoolApi, PoolItem>> for App {
-    fn generate(&self) -> RpcServer<MempoolApi, PoolItem> {
+impl Getter<RpcServer<MempoolApi, SubscriptionItem>> for App {
+    fn generate(&self) -> RpcServer<MempoolApi, SubscriptionItem> {
         let context = self.api.get(self);
         let alive = self.alive.clone();
         let srv_cfg = &self.cfg.get(self).server;
@@ -151,5 +151,6 @@ impl Getter<Simulator> for App {
         let cfg = self.cfg.get(self);
         let el = self.el.get(self);
         Simulator::new(cfg.chain_id, self.alive.clone(), cfg.simulator_thread, el)
+            .with_verify(cfg.verify_state)
     }
 }
@@ -1,9 +1,9 @@
 use std::prelude::v1::*;
 
 use apps::getargs::{Opt, Options};
-use eth_types::{PoolItem, PoolItemType, SH256, SU256, SH160, HexBytes};
+use eth_types::{Bundle, PoolItem, PoolItemType, PoolTx, SH256, SU256, SH160, HexBytes};
 use serde::{Serialize, Deserialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
@@ -16,6 +16,10 @@ pub struct Config {
     pub block_time: u64,
     pub genesis_time: u64,
     pub server: ServerConfig,
+    // verifies the states read by the simulator with proofs, the results of eth_callBundle
+    // are served to the users
+    #[serde(default)]
+    pub verify_state: bool,
 }
 
 #[derive(Debug)]
@@ -66,6 +70,13 @@ pub struct ServerConfig {
     pub ias_apikey: String,
 }
 
+// the items pushed to the subscribers, the bundles carry the txs which can be dropped from them
+#[derive(Debug, Clone)]
+pub enum SubscriptionItem {
+    Tx(PoolTx),
+    Bundle(Bundle, BTreeSet<SH256>),
+}
+
 pub struct MemPoolItem {
     pub ty: PoolItemType,
     pub val: Option<PoolItem>,
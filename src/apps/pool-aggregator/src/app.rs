@@ -3,7 +3,7 @@ use std::prelude::v1::*;
 use apps::{Getter, Var, VarMutex};
 use base::fs::parse_file;
 use base::trace::Alive;
-use block_builder::{BlockStateFetcher, SimulateResult, Simulator};
+use block_builder::{SimulateResult, Simulator};
 use eth_client::{BeaconSlot, HashPool, MempoolItem, TxFetcher};
 use eth_client::{BlockReport, ExecutionClient, HeadState};
 use eth_types::{PoolTx, Signer};
@@ -134,7 +134,6 @@ impl Getter<ExecutionClient> for App {
 
 impl App {
     fn simulate_thread(&self) -> Result<(Receiver<SimulateResult<()>>, JoinHandle<()>), String> {
-        let el = self.el.get(self);
         let (sender, receiver) = mpsc::channel();
         let secs = Duration::from_secs(1);
         let tx_fetcher = self.tx_fetcher.get(self);
@@ -144,13 +143,13 @@ impl App {
         let handle = base::thread::spawn("tx-client".into(), move || {
             tx_fetcher.recv_iter(secs, |item| {
                 let head_blk = head_state.get();
-                let fetcher = BlockStateFetcher::new(el.clone(), head_blk.number.into());
+                let fetcher = simulator.fetcher(&head_blk);
                 let state_db = MapState::new(head_blk.clone(), fetcher);
 
                 let pooltx = match &item {
                     MempoolItem::Price(n) => n,
                     MempoolItem::Seq(n) => n,
-                    MempoolItem::Bundle(_) => return,
+                    MempoolItem::Bundle(..) => return,
                 };
                 simulator.simulate_async(
                     (),
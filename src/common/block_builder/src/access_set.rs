@@ -0,0 +1,172 @@
+use std::prelude::v1::*;
+
+use eth_types::{PoolTx, TransactionAccessTuple, SH160, SH256};
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Default)]
+pub struct AccessSet {
+    accounts: BTreeSet<SH160>,
+    storages: BTreeSet<(SH160, SH256)>,
+}
+
+impl AccessSet {
+    pub fn new<'a, I>(list: I) -> Self
+    where
+        I: Iterator<Item = &'a TransactionAccessTuple>,
+    {
+        let mut set = Self::default();
+        set.extend(list);
+        set
+    }
+
+    // the senders and the recipients are included, their balances and nonces are changed
+    // even if the access lists don't mention them.
+    pub fn from_txs<'a, I>(txs: I) -> Self
+    where
+        I: Iterator<Item = &'a PoolTx>,
+    {
+        let mut set = Self::default();
+        for tx in txs {
+            set.insert_account(tx.caller);
+            if let Some(to) = tx.tx.to() {
+                set.insert_account(to);
+            }
+            set.extend(tx.access_list.iter());
+        }
+        set
+    }
+
+    pub fn insert_account(&mut self, address: SH160) {
+        self.accounts.insert(address);
+    }
+
+    pub fn extend<'a, I>(&mut self, list: I)
+    where
+        I: Iterator<Item = &'a TransactionAccessTuple>,
+    {
+        for item in list {
+            self.accounts.insert(item.address);
+            for key in &item.storage_keys {
+                self.storages.insert((item.address, *key));
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &AccessSet) {
+        self.accounts.extend(other.accounts.iter().cloned());
+        self.storages.extend(other.storages.iter().cloned());
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len() + self.storages.len()
+    }
+
+    // two sets are conflicted if they share a storage slot, or one of them
+    // touches the account itself(balance/nonce/code) which the other one reads.
+    pub fn is_conflict(&self, other: &AccessSet, ignore: &[SH160]) -> bool {
+        let (small, large) = if self.storages.len() < other.storages.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        for item in &small.storages {
+            if ignore.contains(&item.0) {
+                continue;
+            }
+            if large.storages.contains(item) {
+                return true;
+            }
+        }
+
+        for addr in &small.accounts {
+            if ignore.contains(addr) || !large.accounts.contains(addr) {
+                continue;
+            }
+            let small_account_only = small.account_only(addr);
+            let large_account_only = large.account_only(addr);
+            if small_account_only || large_account_only {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn account_only(&self, addr: &SH160) -> bool {
+        self.storages
+            .range((*addr, SH256::default())..)
+            .next()
+            .map(|(n, _)| n != addr)
+            .unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use eth_types::{H160, H256};
+
+    fn addr(n: u8) -> SH160 {
+        H160::from([n; 20]).into()
+    }
+
+    fn key(n: u8) -> SH256 {
+        H256::from([n; 32]).into()
+    }
+
+    fn set(list: &[(u8, &[u8])]) -> AccessSet {
+        let list: Vec<_> = list
+            .iter()
+            .map(|(address, keys)| TransactionAccessTuple {
+                address: addr(*address),
+                storage_keys: keys.iter().map(|n| key(*n)).collect(),
+            })
+            .collect();
+        AccessSet::new(list.iter())
+    }
+
+    #[test]
+    fn test_storage_conflict() {
+        let a = set(&[(1, &[1, 2])]);
+        assert!(a.is_conflict(&set(&[(1, &[2, 3])]), &[]));
+        assert!(!a.is_conflict(&set(&[(1, &[3])]), &[]));
+        // the same slot of another contract
+        assert!(!a.is_conflict(&set(&[(2, &[1])]), &[]));
+        assert!(!a.is_conflict(&set(&[(1, &[2])]), &[addr(1)]));
+    }
+
+    #[test]
+    fn test_account_conflict() {
+        let a = set(&[(1, &[1])]);
+        // the account itself is touched
+        assert!(a.is_conflict(&set(&[(1, &[])]), &[]));
+        assert!(set(&[(1, &[])]).is_conflict(&a, &[]));
+        assert!(!a.is_conflict(&set(&[(2, &[])]), &[]));
+        assert!(!a.is_conflict(&set(&[(1, &[])]), &[addr(1)]));
+    }
+
+    #[test]
+    fn test_sender_conflict() {
+        // two txs of the same sender with empty access lists
+        let mut a = set(&[]);
+        a.insert_account(addr(1));
+        let mut b = set(&[(2, &[1])]);
+        assert!(!a.is_conflict(&b, &[]));
+        b.insert_account(addr(1));
+        assert!(a.is_conflict(&b, &[]));
+
+        // the recipient of a transfer is read by another tx
+        let mut c = set(&[]);
+        c.insert_account(addr(2));
+        assert!(c.is_conflict(&b, &[]));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = set(&[(1, &[1])]);
+        let b = set(&[(2, &[2])]);
+        assert!(!a.is_conflict(&b, &[]));
+        a.merge(&set(&[(2, &[2, 3])]));
+        assert!(a.is_conflict(&b, &[]));
+        assert_eq!(a.len(), 5);
+    }
+}
@@ -1,7 +1,7 @@
 use std::prelude::v1::*;
 
 use super::types::*;
-use super::Simulator;
+use super::{AccessSet, BuildRecord, ConflictGraph, ConsumedPool, Simulator, TraceSource};
 
 use base::format::ternary;
 use base::format::{ether_sub, parse_ether, truncate_ether};
@@ -9,14 +9,19 @@ use base::time::Date;
 use base::trace::{Alive, AvgCounterResult, Slowlog};
 use eth_client::ExecutionClient;
 use eth_types::{
-    Block, BlockHeader, LegacyTx, PoolTx, Receipt, Signer, TransactionAccessTuple,
-    TransactionInner, Withdrawal, SH256, SU256, SU64,
+    Block, BlockHeader, Bundle, DynamicFeeTx, LegacyTx, PoolTx, Receipt, Signer,
+    TransactionAccessTuple, TransactionInner, Withdrawal, SH160, SH256, SU256, SU64, U256,
 };
-use evm_executor::{BlockStateFetcher, ExecuteError, Executor};
+use evm_executor::{
+    BlockStateFetcher, ExecuteError, Executor, Fork, PredictStat, SlotPredictor, StateBackends,
+    StateCache, StateRecorder,
+};
+use mev_boost::{BidContext, BidStrategy};
 use statedb::{StateDB, StateFetcher, TrieMemStore, TrieState, TrieStore};
 use txpool::{TransactionsByPriceAndNonce, TxPool};
 
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 pub struct BlockBuilder {
@@ -24,6 +29,68 @@ pub struct BlockBuilder {
     env: BuildEnv,
     client: Arc<ExecutionClient>,
     _simulator: Arc<Simulator>,
+    prefixes: Mutex<BTreeMap<BuildStrategy, BuildPrefix>>,
+    cache: Option<StateCache>,
+    backends: StateBackends,
+    predictor: Option<SlotPredictor>,
+    // the gas limits found for the payment txs, keyed by the recipient and whether a value is
+    // sent. the payer is the same for all of them.
+    payment_gas: Mutex<BTreeMap<(SH160, bool), u64>>,
+}
+
+// the committed part of the block in the previous round
+struct BuildPrefix {
+    slot: u64,
+    tips_recipient: Option<SH160>,
+    header: BlockHeader,
+    state: TrieState<BlockStateFetcher, TrieMemStore>,
+    fetcher: BlockStateFetcher,
+    txs: Vec<Arc<TransactionInner>>,
+    receipts: Vec<Receipt>,
+    gas_pool: u64,
+    payment_gas: u64,
+    miner_balance: SU256,
+    checked_txs: BTreeMap<SH256, bool>,
+    bundles: BTreeSet<SH256>,
+    fill: FillResult,
+    trace: BuildTrace,
+}
+
+// a conflicted bundle is dropped if its score falls below this percent of the simulated one
+const BUNDLE_SCORE_TOLERANCE: u64 = 90;
+
+// the cached payment gas limits are cleared once they reach this many recipients
+const PAYMENT_GAS_CACHE_SIZE: usize = 1024;
+
+struct SimulatedBundle {
+    bundle: Arc<Bundle>,
+    dropping: BTreeSet<SH256>,
+    score: SU256,
+    access_set: AccessSet,
+    result: SimulatedResult,
+}
+
+// the outcome of the simulation, it can be reused as long as the state is still the one the
+// bundle was simulated against.
+struct SimulatedResult {
+    base: SH256,
+    root: SH256,
+    txs: Vec<(Arc<TransactionInner>, Receipt, bool)>,
+    profit: SU256,
+    refund: SU256,
+    gas: u64,
+}
+
+enum BundleCommit {
+    Success {
+        profit: SU256,
+        refund: SU256,
+        gas: u64,
+    },
+    Reverted {
+        status: String,
+        stop: bool,
+    },
 }
 
 #[derive(Debug, Default)]
@@ -31,17 +98,128 @@ pub struct CommitStat {
     pub removed: u64,
     pub total: u64,
     pub failed: u64,
+    pub skipped: u64,
+    pub resimulated: u64,
     pub state_miss: AvgCounterResult,
 }
 
+// the recipient and the amount of the bundle refund, the percent is validated in
+// `SendBundleRequest::to_bundle`.
+fn bundle_refund(bundle: &Bundle, profit: &SU256) -> Option<(SH160, SU256)> {
+    if bundle.refund_percent == 0 || profit.is_zero() {
+        return None;
+    }
+    let refund = **profit * U256::from(bundle.refund_percent) / U256::from(100u64);
+    Some((bundle.refund_recipient, refund.into()))
+}
+
 impl BlockBuilder {
     pub fn new(cfg: Config, client: Arc<ExecutionClient>, simulator: Arc<Simulator>) -> Self {
         let env = BuildEnv::new(cfg.chain_id);
+        let cache = (cfg.state_cache_size > 0).then(|| StateCache::new(cfg.state_cache_size));
+        let backends = StateBackends::probe(&client, &cfg.state_backends);
+        let predictor =
+            (cfg.slot_predictor_size > 0).then(|| SlotPredictor::new(cfg.slot_predictor_size));
         Self {
             cfg,
             env,
             client,
             _simulator: simulator,
+            prefixes: Mutex::new(BTreeMap::new()),
+            cache,
+            backends,
+            predictor,
+            payment_gas: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn predictor_stat(&self) -> Option<PredictStat> {
+        self.predictor.as_ref().map(|predictor| predictor.stat())
+    }
+
+    // run all the configured strategies concurrently and pick the most profitable block
+    pub fn build_parallel(
+        self: Arc<Self>,
+        alive: &Alive,
+        store: &TrieMemStore,
+        txpool: &Arc<TxPool>,
+        payload: &Arc<BuildPayload>,
+    ) -> Result<BlockResult, BuildError> {
+        let strategies = match self.cfg.strategies.len() {
+            0 => vec![BuildStrategy::default()],
+            _ => self.cfg.strategies.clone(),
+        };
+        if strategies.len() == 1 {
+            let mut result = self.build(alive, store.fork(), txpool, payload, strategies[0])?;
+            result.strategies = vec![StrategyResult::from_result(strategies[0], &Ok(&result))];
+            result.apply_pool_updates(txpool);
+            return Ok(result);
+        }
+
+        let handles = strategies
+            .into_iter()
+            .map(|strategy| {
+                let builder = self.clone();
+                let alive = alive.clone();
+                let store = store.fork();
+                let txpool = txpool.clone();
+                let payload = payload.clone();
+                let handle = base::thread::spawn(format!("build-{:?}", strategy), move || {
+                    builder.build(&alive, store, &txpool, &payload, strategy)
+                });
+                (strategy, handle)
+            })
+            .collect::<Vec<_>>();
+
+        let mut strategies = Vec::with_capacity(handles.len());
+        let mut best: Option<BlockResult> = None;
+        let mut first_err = None;
+        for (strategy, handle) in handles {
+            let result = match handle.join() {
+                Ok(result) => result,
+                Err(_) => Err(BuildError::InternalError(format!(
+                    "build thread panic: {:?}",
+                    strategy
+                ))),
+            };
+            strategies.push(StrategyResult::from_result(strategy, &result.as_ref()));
+            match result {
+                Ok(result) => {
+                    if best
+                        .as_ref()
+                        .map(|n| n.profit < result.profit)
+                        .unwrap_or(true)
+                    {
+                        best = Some(result);
+                    }
+                }
+                Err(err) => {
+                    if first_err.is_none() {
+                        first_err = Some(err);
+                    }
+                }
+            }
+        }
+        glog::info!(
+            "[{}.#{}] strategies: {:?}",
+            payload.slot,
+            payload.round,
+            strategies
+        );
+        match best {
+            Some(mut result) => {
+                glog::info!(
+                    "[{}.#{}] choose strategy: {:?}, profit: {}",
+                    payload.slot,
+                    payload.round,
+                    result.strategy,
+                    parse_ether(&result.profit, 18),
+                );
+                result.strategies = strategies;
+                result.apply_pool_updates(txpool);
+                Ok(result)
+            }
+            None => Err(first_err.unwrap_or(BuildError::NoTx)),
         }
     }
 
@@ -51,16 +229,253 @@ impl BlockBuilder {
         store: TrieMemStore,
         txpool: &TxPool,
         payload: &BuildPayload,
+        strategy: BuildStrategy,
+    ) -> Result<BlockResult, BuildError> {
+        let record_dir = match &self.cfg.record_dir {
+            Some(dir) => dir,
+            None => {
+                return self.build_with(alive, store, txpool, payload, strategy, BuildMode::Live)
+            }
+        };
+
+        let recorder = StateRecorder::new();
+        let fetcher = self
+            .new_fetcher(&payload.base)
+            .with_recorder(recorder.clone());
+        // the resumed prefix isn't recorded, always build from the parent.
+        let mode = BuildMode::Record(fetcher);
+        let mut result = self.build_with(alive, store, txpool, payload, strategy, mode)?;
+        let record = BuildRecord::new(
+            payload,
+            strategy,
+            result.consumed.take().unwrap_or_default(),
+            result.trace.bid,
+            result.block.header.hash(),
+            recorder.snapshot(),
+        );
+        let path = format!(
+            "{}/{}-{}-{:?}.json",
+            record_dir, payload.slot, payload.round, strategy
+        );
+        // serializing the state takes a while, don't hold the block
+        base::thread::spawn(format!("record-{}", payload.slot), move || {
+            if let Err(err) = record.save(&path) {
+                glog::error!("save build record to {} fail: {}", path, err);
+            }
+        });
+        Ok(result)
+    }
+
+    // build the recorded block again without network, the block should be identical.
+    pub fn replay(
+        &self,
+        record: BuildRecord,
+        store: TrieMemStore,
+    ) -> Result<BlockResult, BuildError> {
+        let txpool = record
+            .txpool(&self.env.signer)
+            .map_err(|err| BuildError::InternalError(format!("invalid record: {}", err)))?;
+        let payload = record.payload();
+        let fetcher = BlockStateFetcher::replay(Arc::new(record.state), payload.base.number.into());
+        let result = self.build_with(
+            &Alive::new(),
+            store,
+            &txpool,
+            &payload,
+            record.strategy,
+            BuildMode::Replay(fetcher, record.committed_txs),
+        )?;
+        let hash = result.block.header.hash();
+        if record.block_hash != Some(hash) {
+            return Err(BuildError::InternalError(format!(
+                "replay block mismatch: want {:?}, got {:?}",
+                record.block_hash, hash
+            )));
+        }
+        Ok(result)
+    }
+
+    // re-executes the block on a fresh state of its parent and compares the result with the
+    // header. the receipts (e.g. from eth_getTransactionReceipt) are used to locate the first
+    // diverging tx, otherwise only the txs failed to execute can be located.
+    pub fn validate_block(
+        &self,
+        store: TrieMemStore,
+        block: &Block,
+        receipts: Option<&[Receipt]>,
+    ) -> Result<BlockValidation, BuildError> {
+        let number = block.header.number.as_u64();
+        if number == 0 {
+            return Err(BuildError::InternalError(
+                "the genesis block can't be re-executed".into(),
+            ));
+        }
+        let mut result = BlockValidation {
+            number,
+            hash: block.header.hash(),
+            ..Default::default()
+        };
+        let parent = self
+            .client
+            .get_block_header(SU64::from(number - 1).into())
+            .map_err(|err| BuildError::InternalError(format!("fetch parent fail: {:?}", err)))?;
+        let fetcher = self.new_fetcher(&parent);
+        let mut state = TrieState::new(fetcher, Arc::new(parent), store.fork());
+
+        let mut header = block.header.clone();
+        header.gas_used = SU64::from(0);
+        let rules = self.env.rules(&header)?;
+        self.apply_system_calls(rules.fork, &header, &mut state)?;
+        let mut txs = Vec::with_capacity(block.transactions.len());
+        let mut local_receipts = Vec::with_capacity(block.transactions.len());
+        for (idx, tx) in block.transactions.iter().enumerate() {
+            let tx = tx.clone().inner().ok_or_else(|| {
+                BuildError::InternalError("the block doesn't include the tx bodies".into())
+            })?;
+            let tx = PoolTx::with_tx(&self.env.signer, tx);
+            let exec_ctx = evm_executor::Context {
+                chain_id: &self.cfg.chain_id,
+                caller: &tx.caller,
+                cfg: &rules.cfg,
+                tx: &tx,
+                precompile: &rules.precompile_set,
+                header: &header,
+            };
+            let receipt = match Executor::apply(exec_ctx, &mut state, idx as u64) {
+                Ok(receipt) => receipt,
+                Err(ExecuteError::StateError(err)) => return Err(err.into()),
+                Err(err) => {
+                    result.diverging_tx = Some(DivergingTx {
+                        index: idx as u64,
+                        hash: tx.hash,
+                        reason: format!("{:?}", err),
+                    });
+                    return Ok(result);
+                }
+            };
+            header.gas_used = receipt.cumulative_gas_used;
+
+            // only the first one is reported
+            let expect = receipts.and_then(|receipts| receipts.get(idx));
+            if let Some(expect) = expect.filter(|_| result.diverging_tx.is_none()) {
+                let reason = if expect.status != receipt.status {
+                    Some(format!(
+                        "status: want {:?}, got {:?}",
+                        expect.status, receipt.status
+                    ))
+                } else if expect.cumulative_gas_used != receipt.cumulative_gas_used {
+                    Some(format!(
+                        "cumulative gas used: want {:?}, got {:?}",
+                        expect.cumulative_gas_used, receipt.cumulative_gas_used
+                    ))
+                } else if expect.logs_bloom != receipt.logs_bloom {
+                    Some("logs bloom mismatch".into())
+                } else {
+                    None
+                };
+                result.diverging_tx = reason.map(|reason| DivergingTx {
+                    index: idx as u64,
+                    hash: tx.hash,
+                    reason,
+                });
+            }
+            txs.push(Arc::new(tx.tx));
+            local_receipts.push(receipt);
+        }
+
+        let withdrawals = block.withdrawals.clone().unwrap_or_default();
+        let local =
+            self.finalize_and_assemble(header, &mut state, txs, &mut local_receipts, withdrawals)?;
+        result.check(
+            "state_root",
+            block.header.state_root,
+            local.header.state_root,
+        );
+        result.check(
+            "receipts_root",
+            block.header.receipts_root,
+            local.header.receipts_root,
+        );
+        result.check(
+            "logs_bloom",
+            &block.header.logs_bloom,
+            &local.header.logs_bloom,
+        );
+        result.check("gas_used", block.header.gas_used, local.header.gas_used);
+        Ok(result)
+    }
+
+    // the system calls made before the txs of the block
+    fn apply_system_calls<D: StateDB>(
+        &self,
+        fork: Fork,
+        header: &BlockHeader,
+        state: &mut D,
+    ) -> Result<(), BuildError> {
+        if fork >= Fork::Cancun {
+            let root = header.parent_beacon_block_root.ok_or_else(|| {
+                BuildError::InternalError("missing the parent beacon block root".into())
+            })?;
+            evm_executor::apply_beacon_root(state, header.timestamp.as_u64(), &root)?;
+        }
+        if fork >= Fork::Prague {
+            evm_executor::apply_parent_hash(state, header.number.as_u64(), &header.parent_hash)?;
+        }
+        Ok(())
+    }
+
+    // builds on the remote state and tries to resume from the previous round if the fetcher
+    // is not given, otherwise always builds from the parent with the given fetcher.
+    fn build_with(
+        &self,
+        alive: &Alive,
+        store: TrieMemStore,
+        txpool: &TxPool,
+        payload: &BuildPayload,
+        strategy: BuildStrategy,
+        mode: BuildMode,
     ) -> Result<BlockResult, BuildError> {
         let now = Instant::now();
-        let mut env = self.prepare_work(store, payload);
+        let prefix = match &mode {
+            BuildMode::Live => self.resume_work(&store, txpool, payload, strategy),
+            _ => None,
+        };
+        let (mut env, fill_result, resumed) = match prefix {
+            Some((env, fill_result)) => (env, fill_result, true),
+            None => {
+                let env = match mode {
+                    BuildMode::Live => self.prepare_work(store, payload, None),
+                    BuildMode::Record(fetcher) => {
+                        let mut env = self.prepare_work(store, payload, Some(fetcher));
+                        env.consumed = Some(ConsumedPool::default());
+                        env
+                    }
+                    BuildMode::Replay(fetcher, tx_limit) => {
+                        let mut env = self.prepare_work(store, payload, Some(fetcher));
+                        env.tx_limit = Some(tx_limit);
+                        env
+                    }
+                };
+                (env, FillResult::default(), false)
+            }
+        };
+
+        // the txs can't be executed with the wrong rules
+        self.env.rules(&env.header)?;
+        env.trace.slot = payload.slot;
+        env.trace.number = env.header.number.as_u64();
+        env.trace.round = payload.round;
+        env.trace.strategy = strategy;
 
         glog::info!("{}", "==".repeat(80));
         glog::info!(
-            "\tROUND: #{}, Block: {}, Slot: {}",
+            "\tROUND: #{}, Block: {}, Slot: {}, Strategy: {:?}, Resumed: {}({} txs)",
             payload.round,
             env.header.number,
             payload.slot,
+            strategy,
+            resumed,
+            env.txs.len(),
         );
         glog::info!(
             "\tBlockTime: {}, Pool {{ bundle: {}, seq: {}, price: {} }}, RemainTime: {:?}, Deadline: {:?}",
@@ -73,15 +488,33 @@ impl BlockBuilder {
         );
         glog::info!("{}", "==".repeat(80));
 
-        let fill_result = self.fill_transactions(alive, txpool, &mut env)?;
+        let mut fill_result =
+            self.fill_transactions(alive, txpool, &mut env, strategy, fill_result, resumed)?;
+        let committed_txs = env.txs.len();
+        if let Some(consumed) = &mut env.consumed {
+            consumed.committed_txs = committed_txs;
+        }
+        env.tx_limit = None;
+        self.save_prefix(&mut env, payload, strategy, &fill_result)?;
+        fill_result.profit = self.fill_tips(
+            &mut env,
+            payload,
+            &fill_result.internal_txs,
+            fill_result.bundle_result.len(),
+            &fill_result.refunds,
+        )?;
         let block = self.finalize_and_assemble(
             env.header,
             &mut env.state,
             env.txs,
-            &env.receipts,
+            &mut env.receipts,
             payload.withdrawals.clone(),
         )?;
         env.fetcher.get_miss_usage(); // clean up the dbGet
+        glog::info!("state misses by backend: {:?}", env.fetcher.backend_usage());
+        if let Some(stat) = self.predictor_stat() {
+            glog::info!("slot predictor: {:?}", stat);
+        }
         {
             let header = &block.header;
             let pct = header.gas_used * SU64::from(100u64) / header.gas_limit;
@@ -107,21 +540,47 @@ impl BlockBuilder {
         Ok(BlockResult {
             block,
             slot: payload.slot,
+            strategy,
+            strategies: Vec::new(),
             bundles: fill_result.bundle_result,
             internal_txs: fill_result.internal_txs,
             profit: fill_result.profit,
             receipts: env.receipts,
+            trace: env.trace,
+            pool_updates: env.pool_updates,
+            consumed: env.consumed,
         })
     }
 
+    // reads the states of the given block from the execution node
+    fn new_fetcher(&self, header: &BlockHeader) -> BlockStateFetcher {
+        let fetcher = BlockStateFetcher::new(self.client.clone(), header.number.into())
+            .with_backends(&self.backends)
+            .with_state_root(header.state_root);
+        match self.cfg.verify_state {
+            true => fetcher.with_verify(header.state_root),
+            false => fetcher,
+        }
+    }
+
     fn prepare_work(
         &self,
         store: TrieMemStore,
         payload: &BuildPayload,
+        fetcher: Option<BlockStateFetcher>,
     ) -> Environment<BlockStateFetcher, TrieState<BlockStateFetcher, TrieMemStore>> {
-        let fetcher = BlockStateFetcher::new(self.client.clone(), payload.base.number.into());
+        let fetcher = fetcher.unwrap_or_else(|| {
+            let fetcher = self.new_fetcher(&payload.base);
+            match &self.cache {
+                Some(cache) => {
+                    cache.advance(&self.client, &payload.base);
+                    fetcher.with_cache(cache.clone(), payload.base.hash())
+                }
+                None => fetcher,
+            }
+        });
         let state_db = TrieState::new(fetcher.clone(), payload.base.clone(), store.fork());
-        let header = payload.next_block();
+        let header = payload.next_block(&self.env.spec);
 
         Environment::new(
             fetcher,
@@ -133,28 +592,166 @@ impl BlockBuilder {
         )
     }
 
+    // resume from the committed prefix of the previous round in the same slot
+    fn resume_work(
+        &self,
+        store: &TrieMemStore,
+        txpool: &TxPool,
+        payload: &BuildPayload,
+        strategy: BuildStrategy,
+    ) -> Option<(
+        Environment<BlockStateFetcher, TrieState<BlockStateFetcher, TrieMemStore>>,
+        FillResult,
+    )> {
+        let prefixes = self.prefixes.lock().unwrap();
+        let prefix = prefixes.get(&strategy)?;
+        let header = payload.next_block(&self.env.spec);
+        if prefix.slot != payload.slot
+            || prefix.tips_recipient != payload.tips_recipient
+            || prefix.header.parent_hash != header.parent_hash
+            || prefix.header.gas_limit != header.gas_limit
+            || prefix.header.timestamp != header.timestamp
+            || prefix.header.miner != header.miner
+            || prefix.header.mix_hash != header.mix_hash
+        {
+            return None;
+        }
+        if strategy != BuildStrategy::PriceOnly {
+            // the bundles should be placed before the txs, so we can't append them.
+            let bundles = txpool
+                .bundle_pool
+                .list(header.number, header.timestamp.as_u64());
+            if let Some(item) = bundles
+                .iter()
+                .find(|n| !prefix.bundles.contains(&n.bundle.hash()))
+            {
+                glog::info!(
+                    "[{:?}] new bundle[{:?}] arrived, fallback to full rebuild",
+                    strategy,
+                    item.bundle.hash()
+                );
+                return None;
+            }
+        }
+
+        let mut env = Environment::new(
+            prefix.fetcher.clone(),
+            prefix.state.fork(),
+            prefix.header.clone(),
+            payload.tips_recipient,
+            payload.round,
+            store.fork(),
+        );
+        env.txs = prefix.txs.clone();
+        env.receipts = prefix.receipts.clone();
+        env.gas_pool = prefix.gas_pool;
+        env.payment_gas = prefix.payment_gas;
+        env.miner_balance = prefix.miner_balance;
+        env.checked_txs = prefix.checked_txs.clone();
+        env.trace = prefix.trace.clone();
+        Some((env, prefix.fill.clone()))
+    }
+
+    // keep the committed txs before sending the tips, so the next round can resume from here.
+    fn save_prefix(
+        &self,
+        env: &mut Environment<BlockStateFetcher, TrieState<BlockStateFetcher, TrieMemStore>>,
+        payload: &BuildPayload,
+        strategy: BuildStrategy,
+        fill_result: &FillResult,
+    ) -> Result<(), BuildError> {
+        env.state.flush()?;
+        let prefix = BuildPrefix {
+            slot: payload.slot,
+            tips_recipient: payload.tips_recipient,
+            header: env.header.clone(),
+            state: env.state.fork(),
+            fetcher: env.fetcher.clone(),
+            txs: env.txs.clone(),
+            receipts: env.receipts.clone(),
+            gas_pool: env.gas_pool,
+            payment_gas: env.payment_gas,
+            miner_balance: env.miner_balance,
+            // give the failed txs another chance in the next round
+            checked_txs: env
+                .checked_txs
+                .iter()
+                .filter(|(_, checked)| **checked)
+                .map(|(hash, checked)| (*hash, *checked))
+                .collect(),
+            bundles: fill_result
+                .bundle_result
+                .iter()
+                .map(|n| n.bundle.hash())
+                .collect(),
+            fill: fill_result.clone(),
+            trace: env.trace.clone(),
+        };
+        let mut prefixes = self.prefixes.lock().unwrap();
+        prefixes.retain(|_, item| item.slot == payload.slot);
+        prefixes.insert(strategy, prefix);
+        Ok(())
+    }
+
     fn fill_transactions<F: StateFetcher, D: StateDB>(
         &self,
         alive: &Alive,
         txpool: &TxPool,
         env: &mut Environment<F, D>,
+        strategy: BuildStrategy,
+        mut result: FillResult,
+        resumed: bool,
     ) -> Result<FillResult, BuildError> {
-        const TX_GAS: u64 = 32000;
-        let mut result = FillResult::default();
-
-        env.gas_pool = env.header.gas_limit.as_u64() - TX_GAS;
-
-        self.prefetch_basic_info(env)?;
+        if !resumed {
+            env.payment_gas = self.reserve_payment_gas(env)?;
+            env.gas_pool = env
+                .header
+                .gas_limit
+                .as_u64()
+                .saturating_sub(env.payment_gas);
+            self.prefetch_basic_info(env)?;
+            env.miner_balance = env.state.get_balance(&env.header.miner)?;
+        }
 
-        env.miner_balance = env.state.get_balance(&env.header.miner)?;
-        result.bundle_result = self.fill_bundles(alive, env, &txpool.bundle_pool)?;
-        result.internal_txs = self.fill_seq(alive, env, &txpool.seq_pool)?;
-        let price_stat = self.fill_price(alive, env, &txpool.price_pool)?;
+        let price_stat = match strategy {
+            BuildStrategy::BundlesFirst => {
+                if !resumed {
+                    result.bundle_result = self.fill_bundles(alive, env, &txpool.bundle_pool)?;
+                }
+                let internal_txs = self.fill_seq(alive, env, &txpool.seq_pool)?;
+                result.internal_txs.extend(internal_txs);
+                self.fill_price(alive, env, &txpool.price_pool)?
+            }
+            BuildStrategy::PriceOnly => {
+                let stat = self.fill_interleaved(alive, env, txpool, false)?;
+                result.internal_txs = self.collect_internal_txs(env, &txpool.seq_pool);
+                stat
+            }
+            BuildStrategy::SeqFirst => {
+                let internal_txs = self.fill_seq(alive, env, &txpool.seq_pool)?;
+                result.internal_txs.extend(internal_txs);
+                if !resumed {
+                    result.bundle_result = self.fill_bundles(alive, env, &txpool.bundle_pool)?;
+                }
+                self.fill_price(alive, env, &txpool.price_pool)?
+            }
+            BuildStrategy::InterleavedByTip => {
+                if !resumed {
+                    result.bundle_result = self.fill_bundles(alive, env, &txpool.bundle_pool)?;
+                }
+                let stat = self.fill_interleaved(alive, env, txpool, true)?;
+                result.internal_txs = self.collect_internal_txs(env, &txpool.seq_pool);
+                stat
+            }
+        };
+        result.refunds = SU256::zero();
+        for bundle in &result.bundle_result {
+            result.refunds += bundle.refund;
+        }
         if env.txs.len() == 0 {
             return Err(BuildError::NoTx);
         }
         glog::info!("commit price_stat: {:?}", price_stat);
-        result.profit = self.fill_tips(env, &result.internal_txs, result.bundle_result.len())?;
         Ok(result)
     }
 
@@ -165,7 +762,8 @@ impl BlockBuilder {
     {
         let now = Instant::now();
         let mut accounts = Vec::new();
-        for addr in self.env.precompile_set.get_addresses() {
+        let rules = self.env.rules(&env.header)?;
+        for addr in rules.precompile_set.get_addresses() {
             accounts.push(TransactionAccessTuple::new(addr.into()));
         }
         accounts.push(TransactionAccessTuple::new(env.header.miner));
@@ -194,70 +792,359 @@ impl BlockBuilder {
         let block_number = env.header.number;
         let timestamp = env.header.timestamp.as_u64();
         let bundle_list = pool.list(block_number, timestamp);
-        'next_bundle: for bundle in bundle_list {
-            if !alive.is_alive() {
+        if let Some(consumed) = &mut env.consumed {
+            for item in &bundle_list {
+                consumed.add_bundle(item);
+            }
+        }
+        let bundle_list = bundle_list
+            .into_iter()
+            .map(|item| (item.bundle, item.dropping))
+            .collect();
+        let candidates = self.simulate_bundles(alive, env, bundle_list, &mut bundle_result)?;
+        env.trace.source = TraceSource::Bundle;
+
+        let ignore = [env.header.miner, self.cfg.payer];
+        let mut committed = AccessSet::default();
+        for candidate in candidates {
+            if !alive.is_alive() || env.reached_limit() {
                 break;
             }
-            env.state
-                .prefetch(bundle.txs.iter().map(|n| n.access_list.as_ref()).flatten())?;
-            glog::info!(
-                "[#{}] start bundle[{:?}]: tx:{}, state: {:?} ========",
-                env.round,
-                bundle.hash(),
-                bundle.txs.len(),
-                env.state.state_root(),
-            );
+            let conflict = committed.is_conflict(&candidate.access_set, &ignore);
             let state = env.state.state_root();
             let start_tcount = env.txs.len();
-            for (idx, pool_tx) in bundle.txs.iter().enumerate() {
-                let is_last = idx == bundle.txs.len() - 1;
-                let hash = pool_tx.hash;
-                let mut stop = false;
-                let coinbase_before = env.state.get_balance(&env.header.miner)?;
-                match self.commit_transaction(alive, env, pool_tx) {
-                    Ok(CommitAction::Success(receipt)) => {
-                        let is_succ = receipt.succ();
-                        env.checked_txs.insert(hash, true);
-                        if pool_tx.allow_revert || is_succ {
-                            continue;
-                        }
-                        glog::error!("execute bundle fail[{:?}]: reverted", hash);
+            let start = Instant::now();
+            let commit = if candidate.result.base == state {
+                // nothing is committed since the simulation
+                Self::reuse_simulated(env, &candidate.result);
+                BundleCommit::Success {
+                    profit: candidate.result.profit,
+                    refund: candidate.result.refund,
+                    gas: candidate.result.gas,
+                }
+            } else {
+                self.commit_bundle(alive, env, &candidate.bundle, &candidate.dropping)?
+            };
+            let (profit, refund) = match commit {
+                BundleCommit::Success {
+                    profit,
+                    refund,
+                    gas,
+                } => {
+                    let score = Self::bundle_score(&profit, &refund, gas);
+                    // the bundle is re-simulated on top of the committed bundles, drop it
+                    // if the conflicts make it much worse than the first simulation.
+                    let min_score =
+                        *candidate.score * U256::from(BUNDLE_SCORE_TOLERANCE) / U256::from(100u64);
+                    if conflict && *score < min_score {
+                        glog::info!(
+                            "[#{}] drop bundle[{:?}]: score {} -> {}",
+                            env.round,
+                            candidate.bundle.hash(),
+                            candidate.score,
+                            score,
+                        );
+                        env.state.revert(state);
+                        self.revert_txs_to(env, start_tcount);
+                        let status = format!("conflicted, score: {} -> {}", candidate.score, score);
+                        env.trace.record_bundle(
+                            candidate.bundle.hash(),
+                            "dropped",
+                            Some(status.clone()),
+                            gas,
+                            profit,
+                            refund,
+                            start.elapsed(),
+                        );
+                        bundle_result
+                            .push(BundleResult::reverted(candidate.bundle.clone(), status));
+                        continue;
                     }
-                    Ok(reason) => {
-                        stop = matches!(reason, CommitAction::Stop(_));
-                        glog::error!("execute bundle fail[{:?}]: {:?}", hash, reason);
+                    (profit, refund)
+                }
+                BundleCommit::Reverted { status, stop } => {
+                    bundle_result.push(BundleResult::reverted(candidate.bundle.clone(), status));
+                    if stop {
+                        break;
                     }
-                    Err(err) => return Err(BuildError::StateError(err)),
+                    continue;
                 }
+            };
+            committed.merge(&candidate.access_set);
 
-                // so we revert this bundle
-                env.state.revert(state);
-                self.revert_txs_to(env, start_tcount);
-                bundle_result.push(BundleResult {
-                    bundle: bundle.clone(),
-                    status: format!("reverted in tx: {:?}", pool_tx.hash),
-                });
-                if stop {
-                    break 'next_bundle;
-                }
-                continue 'next_bundle;
-            }
             let new_state = env.state.flush()?;
             env.fetcher.get_miss_usage();
             glog::info!(
-                "[#{}] END bundle[{:?}] success, new state: {:?}",
+                "[#{}] END bundle[{:?}] success, profit: {}, refund: {}, conflict: {}, new state: {:?}",
                 env.round,
-                bundle.hash(),
+                candidate.bundle.hash(),
+                parse_ether(&profit, 18),
+                parse_ether(&refund, 18),
+                conflict,
                 new_state,
             );
-            bundle_result.push(BundleResult {
-                bundle: bundle.clone(),
-                status: "submitted".into(),
-            })
+            bundle_result.push(BundleResult::submitted(
+                candidate.bundle.clone(),
+                profit,
+                refund,
+            ))
         }
         Ok(bundle_result)
     }
 
+    // simulate every bundle against the current state and sort them by the effective gas price.
+    fn simulate_bundles<F, D>(
+        &self,
+        alive: &Alive,
+        env: &mut Environment<F, D>,
+        bundle_list: Vec<(Arc<Bundle>, BTreeSet<SH256>)>,
+        bundle_result: &mut Vec<BundleResult>,
+    ) -> Result<Vec<SimulatedBundle>, BuildError>
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        let now = Instant::now();
+        let total = bundle_list.len();
+        let mut candidates = Vec::with_capacity(total);
+        env.trace.source = TraceSource::BundleSimulation;
+        for (bundle, dropping) in bundle_list {
+            if !alive.is_alive() {
+                break;
+            }
+            let state = env.state.state_root();
+            let start_tcount = env.txs.len();
+            let result = self.commit_bundle(alive, env, &bundle, &dropping)?;
+            match result {
+                BundleCommit::Success {
+                    profit,
+                    refund,
+                    gas,
+                } => {
+                    let root = env.state.flush()?;
+                    let txs = env.txs[start_tcount..]
+                        .iter()
+                        .zip(&env.receipts[start_tcount..])
+                        .map(|(tx, receipt)| {
+                            let hash: SH256 = tx.hash().into();
+                            let checked = env.checked_txs.get(&hash).cloned();
+                            (tx.clone(), receipt.clone(), checked.unwrap_or(true))
+                        })
+                        .collect();
+                    env.state.revert(state);
+                    self.revert_txs_to(env, start_tcount);
+                    candidates.push(SimulatedBundle {
+                        score: Self::bundle_score(&profit, &refund, gas),
+                        access_set: AccessSet::from_txs(bundle.txs.iter()),
+                        bundle,
+                        dropping,
+                        result: SimulatedResult {
+                            base: state,
+                            root,
+                            txs,
+                            profit,
+                            refund,
+                            gas,
+                        },
+                    });
+                }
+                BundleCommit::Reverted { status, stop } => {
+                    bundle_result.push(BundleResult::reverted(bundle, status));
+                    if stop {
+                        break;
+                    }
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.score.cmp(&a.score));
+        glog::info!(
+            "[#{}] simulate bundles: {}/{}, elapsed: {:?}, scores: {:?}",
+            env.round,
+            candidates.len(),
+            total,
+            now.elapsed(),
+            candidates
+                .iter()
+                .map(|n| (n.bundle.hash(), n.score))
+                .collect::<Vec<_>>(),
+        );
+        Ok(candidates)
+    }
+
+    // moves the state to the simulated one instead of executing the bundle again
+    fn reuse_simulated<F, D>(env: &mut Environment<F, D>, result: &SimulatedResult)
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        env.state.revert(result.root);
+        for (tx, receipt, checked) in &result.txs {
+            env.use_gas(receipt.gas_used.as_u64());
+            env.checked_txs.insert(tx.hash().into(), *checked);
+            env.txs.push(tx.clone());
+            env.receipts.push(receipt.clone());
+        }
+    }
+
+    // the effective gas price the coinbase earned from this bundle
+    fn bundle_score(profit: &SU256, refund: &SU256, gas: u64) -> SU256 {
+        if gas == 0 {
+            return SU256::zero();
+        }
+        let profit = profit.saturating_sub(**refund);
+        (profit / U256::from(gas)).into()
+    }
+
+    // commit all the transactions inside the bundle, the state will be reverted if the bundle fails.
+    fn commit_bundle<F, D>(
+        &self,
+        alive: &Alive,
+        env: &mut Environment<F, D>,
+        bundle: &Bundle,
+        dropping: &BTreeSet<SH256>,
+    ) -> Result<BundleCommit, BuildError>
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        let start = Instant::now();
+        let hash = bundle.hash();
+        env.trace.bundle = Some(hash);
+        let result = self.apply_bundle(alive, env, bundle, dropping);
+        env.trace.bundle = None;
+        match &result {
+            Ok(BundleCommit::Success {
+                profit,
+                refund,
+                gas,
+            }) => {
+                env.trace.record_bundle(
+                    hash,
+                    "success",
+                    None,
+                    *gas,
+                    *profit,
+                    *refund,
+                    start.elapsed(),
+                );
+            }
+            Ok(BundleCommit::Reverted { status, .. }) => {
+                env.trace.record_bundle(
+                    hash,
+                    "reverted",
+                    Some(status.clone()),
+                    0,
+                    SU256::zero(),
+                    SU256::zero(),
+                    start.elapsed(),
+                );
+            }
+            Err(_) => {}
+        }
+        result
+    }
+
+    fn apply_bundle<F, D>(
+        &self,
+        alive: &Alive,
+        env: &mut Environment<F, D>,
+        bundle: &Bundle,
+        dropping: &BTreeSet<SH256>,
+    ) -> Result<BundleCommit, BuildError>
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        env.state
+            .prefetch(bundle.txs.iter().map(|n| n.access_list.as_ref()).flatten())?;
+        glog::info!(
+            "[#{}] start bundle[{:?}]: tx:{}, state: {:?} ========",
+            env.round,
+            bundle.hash(),
+            bundle.txs.len(),
+            env.state.state_root(),
+        );
+        let state = env.state.state_root();
+        let start_tcount = env.txs.len();
+        let coinbase_before = env.state.get_balance(&env.header.miner)?;
+        for pool_tx in bundle.txs.iter() {
+            let hash = pool_tx.hash;
+            let mut stop = false;
+            match self.commit_transaction(alive, env, pool_tx) {
+                Ok(CommitAction::Success(receipt)) => {
+                    let is_succ = receipt.succ();
+                    env.checked_txs.insert(hash, true);
+                    if pool_tx.allow_revert || is_succ {
+                        continue;
+                    }
+                    glog::error!("execute bundle fail[{:?}]: reverted", hash);
+                }
+                Ok(reason) => {
+                    let invalid =
+                        matches!(reason, CommitAction::RemoveTx | CommitAction::MarkFail(_));
+                    stop = matches!(reason, CommitAction::Stop(_));
+                    glog::error!("execute bundle fail[{:?}]: {:?}", hash, reason);
+                    if invalid && dropping.contains(&hash) && Self::is_droppable(env, pool_tx)? {
+                        // the tx may already landed, skip it instead of failing the whole bundle
+                        glog::info!("drop bundle tx[{:?}]", hash);
+                        continue;
+                    }
+                }
+                Err(err) => return Err(err.into()),
+            }
+
+            // so we revert this bundle
+            env.state.revert(state);
+            self.revert_txs_to(env, start_tcount);
+            return Ok(BundleCommit::Reverted {
+                status: format!("reverted in tx: {:?}", pool_tx.hash),
+                stop,
+            });
+        }
+
+        let coinbase_after = env.state.get_balance(&env.header.miner)?;
+        let profit: SU256 = coinbase_after.saturating_sub(*coinbase_before).into();
+        let gas = env.receipts[start_tcount..]
+            .iter()
+            .map(|n| n.gas_used.as_u64())
+            .sum();
+        let refund = match self.commit_refund(env, bundle, &profit) {
+            Ok(refund) => refund,
+            Err(err @ (BuildError::StateError(_) | BuildError::InvalidProof(_))) => {
+                return Err(err)
+            }
+            Err(err) => {
+                glog::error!("send bundle refund fail[{:?}]: {:?}", bundle.hash(), err);
+                env.state.revert(state);
+                self.revert_txs_to(env, start_tcount);
+                return Ok(BundleCommit::Reverted {
+                    status: format!("refund fail: {:?}", err),
+                    stop: false,
+                });
+            }
+        };
+        Ok(BundleCommit::Success {
+            profit,
+            refund,
+            gas,
+        })
+    }
+
+    // only the txs which can't be included anymore are dropped: the nonce is too low, or the
+    // sender can't afford it.
+    fn is_droppable<F, D>(env: &mut Environment<F, D>, pool_tx: &PoolTx) -> Result<bool, BuildError>
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        let tx = &pool_tx.tx;
+        if env.state.get_nonce(&pool_tx.caller)? > tx.nonce() {
+            return Ok(true);
+        }
+        let cost = SU256::from(tx.gas_limit()) * *tx.max_fee_per_gas() + tx.value();
+        Ok(env.state.get_balance(&pool_tx.caller)? < cost)
+    }
+
     fn fill_seq<F, D>(
         &self,
         alive: &Alive,
@@ -270,6 +1157,7 @@ impl BlockBuilder {
     {
         let mut internal_tx = Vec::new();
         let internal_list = pool.list_by_seq();
+        env.trace.source = TraceSource::Seq;
         env.state.prefetch(
             internal_list
                 .iter()
@@ -279,6 +1167,13 @@ impl BlockBuilder {
 
         for pool_tx in internal_list {
             let hash = &pool_tx.hash;
+            if env.checked_txs.get(hash) == Some(&true) {
+                // already committed in the resumed prefix
+                continue;
+            }
+            if let Some(consumed) = &mut env.consumed {
+                consumed.add_tx(&pool_tx, TraceSource::Seq);
+            }
             match self.commit_transaction(alive, env, &pool_tx) {
                 Ok(CommitAction::Success(_)) => {
                     if let Some(du) = pool.get_live_time(hash) {
@@ -289,10 +1184,10 @@ impl BlockBuilder {
                     glog::error!("[{}] commit fail: {:?}", hash, other);
                     match other {
                         CommitAction::MarkFail(reason) => {
-                            pool.mark_fail(&hash, reason);
+                            env.pool_updates.push(PoolUpdate::MarkFail(*hash, reason));
                         }
                         CommitAction::RemoveTx => {
-                            pool.remove(&hash);
+                            env.pool_updates.push(PoolUpdate::Remove(*hash));
                         }
                         CommitAction::Stop(_) => {
                             break;
@@ -300,7 +1195,7 @@ impl BlockBuilder {
                         _ => {}
                     }
                 }
-                Err(err) => return Err(BuildError::StateError(err)),
+                Err(err) => return Err(err.into()),
             }
         }
 
@@ -324,50 +1219,90 @@ impl BlockBuilder {
         Ok(stat)
     }
 
+    fn fill_interleaved<F, D>(
+        &self,
+        alive: &Alive,
+        env: &mut Environment<F, D>,
+        pool: &TxPool,
+        seq_first: bool,
+    ) -> Result<CommitStat, BuildError>
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        let flow = InterleavedCommitFlow {
+            signer: self.env.signer,
+            seq_first,
+        };
+        let stat = self.commit_pool(alive, env, pool, flow, 20)?;
+        Ok(stat)
+    }
+
+    fn collect_internal_txs<F, D>(
+        &self,
+        env: &Environment<F, D>,
+        pool: &txpool::SeqPool,
+    ) -> Vec<u64>
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        let mut internal_tx = Vec::new();
+        for tx in &env.txs {
+            if let Some(du) = pool.get_live_time(&tx.hash()) {
+                internal_tx.push(du.as_secs() / 12);
+            }
+        }
+        internal_tx
+    }
+
     fn fill_tips<F, D>(
         &self,
         env: &mut Environment<F, D>,
+        payload: &BuildPayload,
         internals: &[u64],
         bundles: usize,
+        refunds: &SU256,
     ) -> Result<SU256, BuildError>
     where
         F: StateFetcher,
         D: StateDB,
     {
-        let reward = self.calculate_profit(env)?;
+        let reward = self.calculate_profit(env, refunds)?;
 
-        let recipient = match &env.tips_recipient {
+        let recipient = match env.tips_recipient {
             Some(recipient) => recipient,
             None => return Ok(0.into()),
         };
 
-        let code = env.state.get_code(recipient)?;
-        let tx_gas = match code.len() {
-            0 => 21000,
-            _other => 32000,
-        };
-
         let tag = format!("{}#{}", env.header.number, env.round);
-        let amount = self.cfg.tips_strategy.get(&tag, reward, internals, bundles);
-        let mut tips_tx = {
-            let fee = env.header.base_fee_per_gas * SU256::from(tx_gas);
-            if amount < fee {
-                return Err(BuildError::FeeTooLow);
-            }
-            let amount = amount - fee;
-            TransactionInner::Legacy(LegacyTx {
-                nonce: env.state.get_nonce(&self.cfg.payer)?.into(),
-                gas_price: env.header.base_fee_per_gas,
-                gas: tx_gas.into(),
-                to: Some(recipient.clone()).into(),
-                value: amount,
-                ..Default::default()
-            })
+        let ctx = BidContext {
+            tag: &tag,
+            slot: payload.slot,
+            profit: reward,
+            internals,
+            bundles,
+            top_bid: payload.top_bid,
         };
-        tips_tx.sign(&self.cfg.payer_sk, self.cfg.chain_id.as_u64());
-        let pool_tx = PoolTx::with_tx(&self.env.signer, tips_tx);
+        let amount = match &payload.bid_strategy {
+            Some(strategy) => strategy.bid(&ctx),
+            None => self.cfg.tips_strategy.bid(&ctx),
+        };
+        env.trace.bid = amount;
+        let gas_cap = self.cfg.payment.gas_cap(&recipient);
+        let tx_gas = match self.estimate_payment_gas(env, &recipient, amount, gas_cap)? {
+            Some(gas) => gas,
+            None => return Err(BuildError::SendTipsFail("simulation reverted".into())),
+        };
+        let fee = env.header.base_fee_per_gas * SU256::from(tx_gas);
+        if amount < fee {
+            return Err(BuildError::FeeTooLow);
+        }
+        let pool_tx = self.sign_payment_tx(env, &recipient, amount - fee, tx_gas)?;
 
-        env.gas_pool += 32000;
+        env.gas_pool += env.payment_gas;
+        env.payment_gas = 0;
+        env.trace.source = TraceSource::Payment;
 
         match self.commit_transaction(&Alive::new(), env, &pool_tx)? {
             CommitAction::Success(receipt) => {
@@ -380,6 +1315,214 @@ impl BlockBuilder {
         return Ok(pool_tx.tx.value());
     }
 
+    // send part of the bundle profit back to the refund recipient, returns the total
+    // amount(value + fee) which is paid by the payer. the payer may not be the coinbase, the
+    // refund is taken off the profit either way, see `calculate_profit`.
+    fn commit_refund<F, D>(
+        &self,
+        env: &mut Environment<F, D>,
+        bundle: &Bundle,
+        profit: &SU256,
+    ) -> Result<SU256, BuildError>
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        let (recipient, refund) = match bundle_refund(bundle, profit) {
+            Some(refund) => refund,
+            None => return Ok(SU256::zero()),
+        };
+        let gas_cap = self.cfg.payment.gas_cap(&recipient);
+        let tx_gas = match self.estimate_payment_gas(env, &recipient, refund, gas_cap)? {
+            Some(gas) => gas,
+            None => return Err(BuildError::SendRefundFail("simulation reverted".into())),
+        };
+        let fee = env.header.base_fee_per_gas * SU256::from(tx_gas);
+        if refund <= fee {
+            glog::info!(
+                "skip bundle[{:?}] refund: amount({}) can't cover the fee({})",
+                bundle.hash(),
+                parse_ether(&refund, 18),
+                parse_ether(&fee, 18),
+            );
+            return Ok(SU256::zero());
+        }
+
+        let pool_tx = self.sign_payment_tx(env, &recipient, refund - fee, tx_gas)?;
+
+        match self.commit_transaction(&Alive::new(), env, &pool_tx)? {
+            CommitAction::Success(receipt) => {
+                if !receipt.succ() {
+                    return Err(BuildError::SendRefundFail("tx reverted".into()));
+                }
+            }
+            other => return Err(BuildError::SendRefundFail(format!("{:?}", other))),
+        }
+        Ok(refund)
+    }
+
+    // reserves the gas for the tips tx before the amount is known
+    fn reserve_payment_gas<F, D>(&self, env: &mut Environment<F, D>) -> Result<u64, BuildError>
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        let recipient = match env.tips_recipient {
+            Some(recipient) => recipient,
+            None => return Ok(0),
+        };
+        let gas_cap = self.cfg.payment.gas_cap(&recipient);
+        let gas = self.estimate_payment_gas(env, &recipient, SU256::zero(), gas_cap)?;
+        Ok(gas.unwrap_or(gas_cap))
+    }
+
+    // simulates the payment tx against the current state, returns the lowest gas limit it
+    // succeeds with, or None if it's reverted or needs more gas than the cap. the payment to a
+    // recipient mostly costs the same, so the last estimate is confirmed with a single run
+    // before searching again.
+    fn estimate_payment_gas<F, D>(
+        &self,
+        env: &mut Environment<F, D>,
+        recipient: &SH160,
+        value: SU256,
+        gas_cap: u64,
+    ) -> Result<Option<u64>, BuildError>
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        let key = (*recipient, value.is_zero());
+        let cached = self.payment_gas.lock().unwrap().get(&key).cloned();
+        if let Some(gas) = cached.filter(|gas| *gas <= gas_cap) {
+            if self.dry_run_payment(env, recipient, value, gas)?.is_ok() {
+                return Ok(Some(gas));
+            }
+        }
+        let gas = match self.search_payment_gas(env, recipient, value, gas_cap)? {
+            Some(gas) => gas,
+            None => return Ok(None),
+        };
+        let mut estimates = self.payment_gas.lock().unwrap();
+        if estimates.len() >= PAYMENT_GAS_CACHE_SIZE && !estimates.contains_key(&key) {
+            estimates.clear();
+        }
+        estimates.insert(key, gas);
+        Ok(Some(gas))
+    }
+
+    fn search_payment_gas<F, D>(
+        &self,
+        env: &mut Environment<F, D>,
+        recipient: &SH160,
+        value: SU256,
+        gas_cap: u64,
+    ) -> Result<Option<u64>, BuildError>
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        let used_gas = match self.dry_run_payment(env, recipient, value, gas_cap)? {
+            Ok(used_gas) => used_gas,
+            Err(reason) => {
+                glog::error!("simulate payment to {:?} fail: {}", recipient, reason);
+                return Ok(None);
+            }
+        };
+        // the used gas is counted after the refunds, and the inner calls may need more
+        // because of the 63/64 rule, so search the gas limit between it and the cap.
+        let mut lo = used_gas - 1;
+        let mut hi = gas_cap;
+        // most of the payments succeed with a small margin, try it first
+        let guess = used_gas * 64 / 63;
+        if guess < hi {
+            match self.dry_run_payment(env, recipient, value, guess)? {
+                Ok(_) => hi = guess,
+                Err(_) => lo = guess,
+            }
+        }
+        while lo + 1 < hi {
+            let mid = (lo + hi) / 2;
+            match self.dry_run_payment(env, recipient, value, mid)? {
+                Ok(_) => hi = mid,
+                Err(_) => lo = mid,
+            }
+        }
+        Ok(Some(hi))
+    }
+
+    // returns the used gas of the payment tx, or the reason why it fails
+    fn dry_run_payment<F, D>(
+        &self,
+        env: &mut Environment<F, D>,
+        recipient: &SH160,
+        value: SU256,
+        gas: u64,
+    ) -> Result<Result<u64, String>, BuildError>
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        let tx = self.sign_payment_tx(env, recipient, value, gas)?;
+        let rules = self.env.rules(&env.header)?;
+        let ctx = evm_executor::Context {
+            chain_id: &self.cfg.chain_id,
+            caller: &tx.caller,
+            cfg: &rules.cfg,
+            tx: &tx,
+            precompile: &rules.precompile_set,
+            header: &env.header,
+        };
+        let state = env.state.state_root();
+        let result = Executor::dry_run(ctx, &mut env.state);
+        env.state.revert(state);
+        match result {
+            Ok(result) if result.success => Ok(Ok(result.used_gas.as_u64())),
+            Ok(result) => Ok(Err(format!(
+                "reverted: {}",
+                String::from_utf8_lossy(&result.err)
+            ))),
+            Err(ExecuteError::StateError(err)) => Err(err.into()),
+            Err(err) => Ok(Err(format!("{:?}", err))),
+        }
+    }
+
+    fn sign_payment_tx<F, D>(
+        &self,
+        env: &mut Environment<F, D>,
+        recipient: &SH160,
+        value: SU256,
+        gas: u64,
+    ) -> Result<PoolTx, BuildError>
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        let nonce = env.state.get_nonce(&self.cfg.payer)?;
+        let mut tx = if self.cfg.payment.dynamic_fee {
+            TransactionInner::DynamicFee(DynamicFeeTx {
+                chain_id: self.cfg.chain_id,
+                nonce: nonce.into(),
+                max_priority_fee_per_gas: SU256::zero(),
+                max_fee_per_gas: env.header.base_fee_per_gas,
+                gas: gas.into(),
+                to: Some(recipient.clone()).into(),
+                value,
+                ..Default::default()
+            })
+        } else {
+            TransactionInner::Legacy(LegacyTx {
+                nonce: nonce.into(),
+                gas_price: env.header.base_fee_per_gas,
+                gas: gas.into(),
+                to: Some(recipient.clone()).into(),
+                value,
+                ..Default::default()
+            })
+        };
+        tx.sign(&self.cfg.payer_sk, self.cfg.chain_id.as_u64());
+        Ok(PoolTx::with_tx(&self.env.signer, tx))
+    }
+
     fn revert_txs_to<F, D>(&self, env: &mut Environment<F, D>, at: usize)
     where
         F: StateFetcher,
@@ -396,15 +1539,24 @@ impl BlockBuilder {
         }
     }
 
-    fn calculate_profit<F, D>(&self, env: &mut Environment<F, D>) -> Result<SU256, BuildError>
+    fn calculate_profit<F, D>(
+        &self,
+        env: &mut Environment<F, D>,
+        refunds: &SU256,
+    ) -> Result<SU256, BuildError>
     where
         F: StateFetcher,
         D: StateDB,
     {
         let current_miner_balance = env.state.get_balance(&env.header.miner)?;
-        let balance_changed: SU256 = current_miner_balance
+        let mut balance_changed: SU256 = current_miner_balance
             .saturating_sub(*env.miner_balance)
             .into();
+        // the refunds are sent by the payer, they are only deducted from the miner balance if
+        // the payer is the coinbase.
+        if self.cfg.payer == env.header.miner {
+            balance_changed += *refunds;
+        }
         let tx_tips = {
             let mut total_tips = SU256::zero();
             for (idx, tx) in env.txs.iter().enumerate() {
@@ -423,11 +1575,15 @@ impl BlockBuilder {
             }
             total_tips
         };
-        let profit = tx_tips.min(balance_changed);
+        let profit: SU256 = tx_tips
+            .min(balance_changed)
+            .saturating_sub(**refunds)
+            .into();
         glog::info!(
-            "calculated profit: balance_changed: {}, txfee: {}",
+            "calculated profit: balance_changed: {}, txfee: {}, refunds: {}",
             parse_ether(&balance_changed, 18),
-            parse_ether(&tx_tips, 18)
+            parse_ether(&tx_tips, 18),
+            parse_ether(refunds, 18),
         );
         Ok(profit)
     }
@@ -445,21 +1601,35 @@ impl BlockBuilder {
         // glog::info!("start tx: {:?}", pool_tx.hash);
         const TX_GAS: u64 = 21000;
         let tx = &pool_tx.tx;
+        let tx_start = Instant::now();
         env.checked_txs.insert(tx.hash().into(), false);
-        if env.gas_pool <= TX_GAS {
-            return Ok(CommitAction::Stop(
+        let action = if env.gas_pool <= TX_GAS {
+            Some(CommitAction::Stop(
                 "Not enough gas for further transactions".into(),
-            ));
-        }
-        if env.gas_pool < tx.gas_limit() {
-            return Ok(CommitAction::Pop(format!(
+            ))
+        } else if env.gas_pool < tx.gas_limit() {
+            Some(CommitAction::Pop(format!(
                 "gas pool out of limited, want:{}, remain: {}",
                 tx.gas_limit(),
                 env.gas_pool,
-            )));
-        }
-        if !alive.is_alive() {
-            return Ok(CommitAction::Stop(format!("not alive(maybe timeout)",)));
+            )))
+        } else if !alive.is_alive() {
+            Some(CommitAction::Stop(format!("not alive(maybe timeout)",)))
+        } else if env.reached_limit() {
+            Some(CommitAction::Stop("reached the recorded tx count".into()))
+        } else {
+            None
+        };
+        if let Some(action) = action {
+            env.trace.record_tx(
+                pool_tx.hash,
+                &action,
+                0,
+                SU256::zero(),
+                tx_start.elapsed(),
+                0,
+            );
+            return Ok(action);
         }
         let effective_gas_tip = match tx.effective_gas_tip(Some(&env.header.base_fee_per_gas)) {
             Some(n) => n,
@@ -470,10 +1640,16 @@ impl BlockBuilder {
                     block_base_fee_gwei: parse_ether(&env.header.base_fee_per_gas, 9),
                     base_fee_gwei: parse_ether(&tx.effective_gas_tip(None).unwrap(), 9),
                 };
-                return Ok(CommitAction::MarkFail(format!(
-                    "invalid base fee: {:?}",
-                    err
-                )));
+                let action = CommitAction::MarkFail(format!("invalid base fee: {:?}", err));
+                env.trace.record_tx(
+                    pool_tx.hash,
+                    &action,
+                    0,
+                    SU256::zero(),
+                    tx_start.elapsed(),
+                    0,
+                );
+                return Ok(action);
             }
         };
 
@@ -482,16 +1658,32 @@ impl BlockBuilder {
 
         // TODO: check whether tx protected
         let tx_idx = env.txs.len() as u64;
-        let tx_start = Instant::now();
+        let rules = match self.env.rules(&env.header) {
+            Ok(rules) => rules,
+            // checked before the build starts
+            Err(err) => {
+                let action = CommitAction::Stop(format!("{:?}", err));
+                env.trace.record_tx(
+                    pool_tx.hash,
+                    &action,
+                    0,
+                    SU256::zero(),
+                    tx_start.elapsed(),
+                    0,
+                );
+                return Ok(action);
+            }
+        };
         let exec_ctx = evm_executor::Context {
             chain_id: &self.cfg.chain_id,
             caller: &caller,
-            cfg: &self.env.cfg,
+            cfg: &rules.cfg,
             tx: &pool_tx,
-            precompile: &self.env.precompile_set,
+            precompile: &rules.precompile_set,
             header: &env.header,
         };
-        let receipt = match Executor::apply(exec_ctx, &mut env.state, tx_idx) {
+        let predictor = self.predictor.as_ref();
+        let receipt = match Executor::apply_with(exec_ctx, &mut env.state, tx_idx, predictor) {
             Ok(receipt) => {
                 env.use_gas(receipt.gas_used.as_u64());
                 env.txs.push(tx.clone());
@@ -499,21 +1691,36 @@ impl BlockBuilder {
                 env.receipts.last().unwrap()
             }
             Err(err) => {
-                match err {
-                    ExecuteError::NonceTooLow | ExecuteError::NotSupported => {
+                let action = match err {
+                    ExecuteError::NonceTooLow
+                    | ExecuteError::NotSupported
+                    | ExecuteError::IntrinsicGasTooLow { .. }
+                    | ExecuteError::MaxInitCodeSizeExceeded { .. }
+                    | ExecuteError::TipAboveFeeCap { .. }
+                    | ExecuteError::GasLimitExceeded { .. } => {
                         env.checked_txs.insert(tx.hash().into(), true);
-                        return Ok(CommitAction::RemoveTx);
+                        CommitAction::RemoveTx
+                    }
+                    ExecuteError::NonceTooHigh { .. }
+                    | ExecuteError::ExecutePaymentTxFail(_)
+                    | ExecuteError::InsufficientBaseFee { .. }
+                    | ExecuteError::InsufficientFunds => {
+                        CommitAction::MarkFail(format!("{:?}", err))
                     }
-                    ExecuteError::NonceTooHigh { .. } => {}
-                    ExecuteError::ExecutePaymentTxFail(_) => {}
-                    ExecuteError::InsufficientBaseFee { .. } => {}
-                    ExecuteError::InsufficientFunds => {}
                     ExecuteError::StateError(err) => {
                         return Err(err);
                     }
-                }
-
-                return Ok(CommitAction::MarkFail(format!("{:?}", err)));
+                };
+                let network = env.fetcher.get_miss_usage();
+                env.trace.record_tx(
+                    pool_tx.hash,
+                    &action,
+                    0,
+                    effective_gas_tip,
+                    tx_start.elapsed(),
+                    network.cnt as u64,
+                );
+                return Ok(action);
             }
         };
 
@@ -557,7 +1764,16 @@ impl BlockBuilder {
         );
 
         env.checked_txs.insert(tx.hash().into(), true);
-        return Ok(CommitAction::Success(receipt));
+        let action = CommitAction::Success(receipt);
+        env.trace.record_tx(
+            tx_hash,
+            &action,
+            receipt.gas_used.as_u64(),
+            effective_gas_tip,
+            tx_start.elapsed(),
+            network.cnt as u64,
+        );
+        return Ok(action);
     }
 
     pub fn finalize_and_assemble<D>(
@@ -565,7 +1781,7 @@ impl BlockBuilder {
         mut header: BlockHeader,
         state: &mut D,
         txs: Vec<Arc<TransactionInner>>,
-        receipts: &[Receipt],
+        receipts: &mut [Receipt],
         withdrawals: Vec<Withdrawal>,
     ) -> Result<Block, BuildError>
     where
@@ -584,7 +1800,24 @@ impl BlockBuilder {
         }
 
         header.state_root = state.flush()?;
-        Ok(Block::new(header, txs, receipts, Some(withdrawals)))
+        let block = Block::new(header, txs, receipts, Some(withdrawals));
+
+        // the fields below are not part of the consensus encoding, fill them once the
+        // block hash is known
+        let block_hash = block.header.hash();
+        let mut log_index = 0u64;
+        for receipt in receipts.iter_mut() {
+            receipt.block_hash = Some(block_hash);
+            receipt.block_number = Some(block.header.number);
+            for log in &mut receipt.logs {
+                log.block_hash = block_hash;
+                log.block_number = block.header.number;
+                log.transaction_index = receipt.transaction_index;
+                log.log_index = log_index.into();
+                log_index += 1;
+            }
+        }
+        Ok(block)
     }
 
     fn commit_pool<P, F, D>(
@@ -605,8 +1838,9 @@ impl BlockBuilder {
             let _trace = Slowlog::new_ms("generate txpool list", 10);
             flow.on_fetch_pool_list(env, pool)
         };
+        let mut graph = ConflictGraph::new(vec![env.header.miner, self.cfg.payer]);
 
-        'nextPage: while alive.is_alive() {
+        'nextPage: while alive.is_alive() && !env.reached_limit() {
             let tx_list = flow.peekn(&list, limit);
             glog::info!(
                 "peek tx: {}, checked: {}",
@@ -616,12 +1850,24 @@ impl BlockBuilder {
             if tx_list.len() == 0 {
                 break 'nextPage;
             }
+            if let Some(consumed) = &mut env.consumed {
+                for tx in &tx_list {
+                    consumed.add_tx(tx, flow.source(pool, &tx.hash));
+                }
+            }
+
+            graph.extend(&tx_list);
+            let (validated, unchecked): (Vec<_>, Vec<_>) = tx_list
+                .iter()
+                .cloned()
+                .partition(|tx| graph.is_validated(&tx.hash));
+            stat.skipped += validated.len() as u64;
 
             {
                 // we get all the nonce
                 // TODO: we don't need to get code
 
-                let callers = tx_list
+                let callers = unchecked
                     .iter()
                     .map(|n| TransactionAccessTuple::new(n.caller.clone()))
                     .collect::<Vec<_>>();
@@ -630,15 +1876,40 @@ impl BlockBuilder {
 
             {
                 // prefetch, we should skip those txs which nonce is mismatch
-                let valid_list = self.filter_nonce(env, &tx_list)?;
-
-                // TODO: if the simulation is failed(reverted), we can simulate them again concurrently base on current state.
+                let valid_list = self.filter_nonce(env, &unchecked)?;
 
                 let acls = valid_list
                     .iter()
                     .map(|item| item.access_list.iter())
                     .flatten();
                 env.state.prefetch(acls)?;
+
+                // the txs arrived without simulation, prefetch the slots they are likely to read
+                if let Some(predictor) = &self.predictor {
+                    let predicted = valid_list
+                        .iter()
+                        .filter(|item| item.access_list.is_empty())
+                        .flat_map(|item| predictor.predict(&item.tx))
+                        .collect::<Vec<_>>();
+                    env.state.prefetch(predicted.iter())?;
+                }
+
+                // the state they depend on was changed by the committed txs,
+                // simulate them again so we can prefetch the new access list.
+                let dirty = valid_list
+                    .iter()
+                    .filter(|tx| graph.is_dirty(&tx.hash))
+                    .collect::<Vec<_>>();
+                if dirty.len() > 0 {
+                    env.state.flush()?;
+                }
+                for tx in dirty {
+                    stat.resimulated += 1;
+                    self.resimulate(env, tx)?;
+                }
+                for tx in &valid_list {
+                    graph.mark_validated(&tx.hash);
+                }
             }
 
             loop {
@@ -653,11 +1924,13 @@ impl BlockBuilder {
                     continue 'nextPage;
                 }
 
+                env.trace.source = flow.source(pool, &pool_tx.hash);
                 let commit_action = self.commit_transaction(alive, env, pool_tx);
                 // glog::info!("{:?}act: {:?}", pool_tx.hash, commit_action);
                 match commit_action {
                     Ok(CommitAction::Success(_)) => {
                         stat.total += 1;
+                        graph.commit(&pool_tx.hash);
                         // current_run += 1;
                         flow.shift(&mut list);
                     }
@@ -669,7 +1942,7 @@ impl BlockBuilder {
                     }
                     Ok(CommitAction::RemoveTx) => {
                         stat.removed += 1;
-                        flow.remove_tx(pool, &pool_tx.hash);
+                        env.pool_updates.push(PoolUpdate::Remove(pool_tx.hash));
                         flow.shift(&mut list);
                     }
                     Ok(CommitAction::Stop(_)) => {
@@ -689,6 +1962,34 @@ impl BlockBuilder {
         Ok(stat)
     }
 
+    fn resimulate<F, D>(&self, env: &mut Environment<F, D>, tx: &PoolTx) -> Result<(), BuildError>
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        let mut state = env.state.fork();
+        let rules = self.env.rules(&env.header)?;
+        let ctx = evm_executor::Context {
+            chain_id: &self.cfg.chain_id,
+            caller: &tx.caller,
+            cfg: &rules.cfg,
+            tx,
+            precompile: &rules.precompile_set,
+            header: &env.header,
+        };
+        match Executor::dry_run(ctx, &mut state) {
+            Ok(_) => {}
+            Err(ExecuteError::StateError(err)) => return Err(err.into()),
+            Err(err) => {
+                glog::debug!(target: "conflict", "resimulate tx[{:?}] fail: {:?}", tx.hash, err);
+                return Ok(());
+            }
+        }
+        let access_list = state.export_access_list(Some(&SH160::default()));
+        env.state.prefetch(access_list.iter())?;
+        Ok(())
+    }
+
     fn filter_nonce<F, D>(
         &self,
         env: &mut Environment<F, D>,
@@ -718,6 +2019,14 @@ impl BlockBuilder {
     }
 }
 
+enum BuildMode {
+    Live,
+    // reads the state with the recorder attached
+    Record(BlockStateFetcher),
+    // reads the recorded state, stops at the recorded tx count
+    Replay(BlockStateFetcher, usize),
+}
+
 pub trait CommitFlow {
     type Pool;
     type PoolOrderList;
@@ -733,7 +2042,7 @@ pub trait CommitFlow {
     fn peek<'a>(&self, list: &'a Self::PoolOrderList) -> Option<&'a PoolTx>;
     fn shift(&self, list: &mut Self::PoolOrderList);
     fn pop(&self, list: &mut Self::PoolOrderList);
-    fn remove_tx(&self, pool: &Self::Pool, hash: &SH256) -> bool;
+    fn source(&self, pool: &Self::Pool, hash: &SH256) -> TraceSource;
 }
 
 pub struct PricePoolCommitFlow {
@@ -777,7 +2086,125 @@ impl CommitFlow for PricePoolCommitFlow {
         list.shift()
     }
 
-    fn remove_tx(&self, pool: &Self::Pool, hash: &SH256) -> bool {
-        pool.remove(hash)
+    fn source(&self, _: &Self::Pool, _: &SH256) -> TraceSource {
+        TraceSource::Price
+    }
+}
+
+// merge the seq pool into the price pool, so all of them are ordered by the effective tip.
+pub struct InterleavedCommitFlow {
+    signer: Signer,
+    // the seq tx replaces the price tx of the same sender and nonce, otherwise the one with
+    // the higher effective tip is kept.
+    seq_first: bool,
+}
+
+impl CommitFlow for InterleavedCommitFlow {
+    type Pool = TxPool;
+    type PoolOrderList = TransactionsByPriceAndNonce;
+
+    fn on_fetch_pool_list<F, D>(
+        &self,
+        env: &mut Environment<F, D>,
+        pool: &Self::Pool,
+    ) -> Self::PoolOrderList
+    where
+        F: StateFetcher,
+        D: StateDB,
+    {
+        let mut list = pool.price_pool.list(
+            Some(&mut env.checked_txs),
+            Some(env.header.base_fee_per_gas),
+            usize::max_value(),
+        );
+        let base_fee = env.header.base_fee_per_gas;
+        let tip = |tx: &PoolTx| tx.tx.effective_gas_tip(Some(&base_fee));
+        for tx in pool.seq_pool.list_by_seq() {
+            if env.checked_txs.contains_key(&tx.hash) {
+                continue;
+            }
+            let txs = list.entry(tx.caller).or_insert_with(Vec::new);
+            match txs.iter().position(|n| n.tx.nonce() == tx.tx.nonce()) {
+                Some(idx) if self.seq_first || tip(&tx) > tip(&txs[idx]) => txs[idx] = Arc::new(tx),
+                Some(_) => {}
+                None => txs.push(Arc::new(tx)),
+            }
+        }
+        for txs in list.values_mut() {
+            txs.sort_by_key(|n| n.tx.nonce());
+        }
+        TransactionsByPriceAndNonce::new(self.signer.clone(), list, env.header.base_fee_per_gas)
+    }
+
+    fn peekn(&self, list: &Self::PoolOrderList, n: usize) -> Vec<Arc<PoolTx>> {
+        list.peekn(n)
+    }
+
+    fn peek<'a>(&self, list: &'a Self::PoolOrderList) -> Option<&'a PoolTx> {
+        list.peek()
+    }
+
+    fn pop(&self, list: &mut Self::PoolOrderList) {
+        list.pop()
+    }
+
+    fn shift(&self, list: &mut Self::PoolOrderList) {
+        list.shift()
+    }
+
+    fn source(&self, pool: &Self::Pool, hash: &SH256) -> TraceSource {
+        match pool.seq_pool.get_live_time(hash) {
+            Some(_) => TraceSource::Seq,
+            None => TraceSource::Price,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use eth_types::H160;
+
+    fn bundle(refund_percent: u64, refund_recipient: SH160) -> Bundle {
+        Bundle {
+            txs: Vec::new(),
+            block_number: 1.into(),
+            min_timestamp: None,
+            max_timestamp: None,
+            uuid: "".into(),
+            refund_percent,
+            refund_recipient,
+        }
+    }
+
+    #[test]
+    fn test_bundle_refund() {
+        let recipient = SH160::from(H160::from([1_u8; 20]));
+        let profit = SU256::from(1000_u64);
+
+        assert_eq!(bundle_refund(&bundle(0, recipient), &profit), None);
+        assert_eq!(bundle_refund(&bundle(50, recipient), &SU256::zero()), None);
+        assert_eq!(
+            bundle_refund(&bundle(90, recipient), &profit),
+            Some((recipient, SU256::from(900_u64)))
+        );
+        assert_eq!(
+            bundle_refund(&bundle(99, recipient), &SU256::from(1_u64)),
+            Some((recipient, SU256::zero()))
+        );
+    }
+
+    #[test]
+    fn test_refund_lowers_bundle_profit() {
+        let bundle = Arc::new(bundle(90, SH160::from(H160::from([1_u8; 20]))));
+        let profit = SU256::from(1000_u64);
+        let (_, refund) = bundle_refund(&bundle, &profit).unwrap();
+
+        let result = BundleResult::submitted(bundle.clone(), profit, refund);
+        assert_eq!(result.profit, SU256::from(100_u64));
+        assert_eq!(result.refund, SU256::from(900_u64));
+
+        let result = BundleResult::submitted(bundle, profit, SU256::zero());
+        assert_eq!(result.profit, profit);
     }
 }
@@ -0,0 +1,180 @@
+use std::prelude::v1::*;
+
+use super::AccessSet;
+use eth_types::{PoolTx, SH160, SH256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+// The access lists come from the simulation, so it doesn't distinguish the read and the
+// write. Two txs are treated as conflicted once they share a slot, or one of them touches an
+// account the other one accesses, see `AccessSet::is_conflict`. The sender and the recipient
+// are always touched.
+#[derive(Debug, Default)]
+pub struct ConflictGraph {
+    ignore: Vec<SH160>,
+    index: BTreeMap<SH256, usize>,
+    nodes: Vec<ConflictNode>,
+}
+
+#[derive(Debug)]
+struct ConflictNode {
+    hash: SH256,
+    access_set: AccessSet,
+    edges: BTreeSet<usize>,
+    validated: bool,
+    dirty: bool,
+}
+
+impl ConflictGraph {
+    pub fn new(ignore: Vec<SH160>) -> Self {
+        Self {
+            ignore,
+            index: BTreeMap::new(),
+            nodes: Vec::new(),
+        }
+    }
+
+    pub fn extend(&mut self, txs: &[Arc<PoolTx>]) {
+        for tx in txs {
+            if self.index.contains_key(&tx.hash) {
+                continue;
+            }
+            let access_set = AccessSet::from_txs(std::iter::once(tx.as_ref()));
+            self.insert(tx.hash, access_set);
+        }
+    }
+
+    fn insert(&mut self, hash: SH256, access_set: AccessSet) {
+        let idx = self.nodes.len();
+        let mut edges = BTreeSet::new();
+        for (other_idx, other) in self.nodes.iter_mut().enumerate() {
+            if other.access_set.is_conflict(&access_set, &self.ignore) {
+                other.edges.insert(idx);
+                edges.insert(other_idx);
+            }
+        }
+        self.index.insert(hash, idx);
+        self.nodes.push(ConflictNode {
+            hash,
+            access_set,
+            edges,
+            validated: false,
+            dirty: false,
+        });
+    }
+
+    // the nonce and the prefetch of this tx are still valid
+    pub fn is_validated(&self, hash: &SH256) -> bool {
+        match self.get(hash) {
+            Some(node) => node.validated && !node.dirty,
+            None => false,
+        }
+    }
+
+    pub fn is_dirty(&self, hash: &SH256) -> bool {
+        self.get(hash).map(|n| n.dirty).unwrap_or(false)
+    }
+
+    pub fn mark_validated(&mut self, hash: &SH256) {
+        if let Some(idx) = self.index.get(hash) {
+            let node = &mut self.nodes[*idx];
+            node.validated = true;
+            node.dirty = false;
+        }
+    }
+
+    // the tx changed the state, returns all the txs need to be re-simulated.
+    pub fn commit(&mut self, hash: &SH256) -> Vec<SH256> {
+        let idx = match self.index.get(hash) {
+            Some(idx) => *idx,
+            None => return Vec::new(),
+        };
+        let edges = self.nodes[idx].edges.iter().cloned().collect::<Vec<_>>();
+        let mut dirty = Vec::with_capacity(edges.len());
+        for edge in edges {
+            let node = &mut self.nodes[edge];
+            if !node.dirty {
+                node.dirty = true;
+                dirty.push(node.hash);
+            }
+        }
+        dirty
+    }
+
+    fn get(&self, hash: &SH256) -> Option<&ConflictNode> {
+        self.index.get(hash).map(|idx| &self.nodes[*idx])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use eth_types::{TransactionAccessTuple, H160, H256};
+
+    fn addr(n: u8) -> SH160 {
+        H160::from([n; 20]).into()
+    }
+
+    fn hash(n: u8) -> SH256 {
+        H256::from([n; 32]).into()
+    }
+
+    // a tx sent by `sender` which reads the given slots of the contract 0xff
+    fn access_set(sender: u8, slots: &[u8]) -> AccessSet {
+        let list = vec![TransactionAccessTuple {
+            address: addr(0xff),
+            storage_keys: slots.iter().map(|n| hash(*n)).collect(),
+        }];
+        let mut set = AccessSet::new(list.iter());
+        set.insert_account(addr(sender));
+        set
+    }
+
+    fn graph(txs: &[(u8, AccessSet)]) -> ConflictGraph {
+        let mut graph = ConflictGraph::new(vec![addr(0xee)]);
+        for (n, set) in txs {
+            graph.insert(hash(*n), set.clone());
+        }
+        graph
+    }
+
+    #[test]
+    fn test_commit_marks_conflicts_dirty() {
+        let mut graph = graph(&[
+            (1, access_set(1, &[1])),
+            (2, access_set(2, &[1, 2])),
+            (3, access_set(3, &[3])),
+            // the same sender as tx 1
+            (4, access_set(1, &[4])),
+        ]);
+        for n in 1..=4 {
+            assert!(!graph.is_validated(&hash(n)));
+            graph.mark_validated(&hash(n));
+            assert!(graph.is_validated(&hash(n)));
+        }
+
+        assert_eq!(graph.commit(&hash(1)), vec![hash(2), hash(4)]);
+        assert!(graph.is_dirty(&hash(2)));
+        assert!(!graph.is_validated(&hash(2)));
+        assert!(!graph.is_dirty(&hash(3)));
+        assert!(graph.is_validated(&hash(3)));
+
+        // already dirty
+        assert_eq!(graph.commit(&hash(1)), Vec::<SH256>::new());
+        graph.mark_validated(&hash(2));
+        assert!(!graph.is_dirty(&hash(2)));
+        assert_eq!(graph.commit(&hash(4)), vec![hash(1)]);
+    }
+
+    #[test]
+    fn test_ignored_accounts() {
+        let mut a = access_set(1, &[1]);
+        a.insert_account(addr(0xee));
+        let mut b = access_set(2, &[2]);
+        b.insert_account(addr(0xee));
+        let mut graph = graph(&[(1, a), (2, b)]);
+        assert_eq!(graph.commit(&hash(1)), Vec::<SH256>::new());
+        assert_eq!(graph.commit(&hash(3)), Vec::<SH256>::new());
+        assert!(!graph.is_dirty(&hash(3)));
+    }
+}
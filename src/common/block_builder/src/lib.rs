@@ -7,7 +7,15 @@ mod simulator;
 pub use simulator::*;
 mod types;
 pub use types::*;
+mod access_set;
+pub use access_set::*;
+mod conflict_graph;
+pub use conflict_graph::*;
+mod trace;
+pub use trace::*;
+mod record;
+pub use record::*;
 mod block_builder;
 pub use block_builder::*;
 
-pub use evm_executor::BlockStateFetcher;
\ No newline at end of file
+pub use evm_executor::{BlockStateFetcher, PredictStat, TraceConfig, TraceResult};
\ No newline at end of file
@@ -0,0 +1,179 @@
+use std::prelude::v1::*;
+
+use super::{BuildPayload, BuildStrategy, TraceSource};
+use base::format::debug;
+use base::time::SignedDuration;
+use eth_types::{
+    BlockHeader, Bundle, BundleRlp, HexBytes, PoolTx, PoolTxRlp, Signer, Withdrawal, SH160, SH256,
+    SU256, SU64,
+};
+use evm_executor::StateRecord;
+use mev_boost::{BidContext, BidStrategy};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use txpool::{PoolBundle, TxPool};
+
+// everything a build reads, so it can be replayed offline to get the same block.
+#[derive(Serialize, Deserialize)]
+pub struct BuildRecord {
+    pub strategy: BuildStrategy,
+    pub payload: PayloadRecord,
+    pub bundles: Vec<BundleRecord>,
+    pub seq_txs: Vec<PoolTxRlp>,
+    pub price_txs: Vec<PoolTxRlp>,
+    // the bid strategy can't be recorded, so we keep its decision instead
+    pub bid: Option<SU256>,
+    pub block_hash: Option<SH256>,
+    // the txs committed before the payment tx, the replay stops here instead of the deadline
+    pub committed_txs: usize,
+    pub state: StateRecord,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayloadRecord {
+    pub round: usize,
+    pub slot: u64,
+    pub base: BlockHeader,
+    pub coinbase: SH160,
+    pub gas_limit: SU64,
+    pub timestamp: u64,
+    pub random: SH256,
+    pub extra: HexBytes,
+    pub withdrawals: Vec<Withdrawal>,
+    pub tips_recipient: Option<SH160>,
+    pub top_bid: Option<SU256>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BundleRecord {
+    pub bundle: BundleRlp,
+    pub dropping: Vec<SH256>,
+}
+
+// the pool items the build has looked at, the others can't affect the block.
+#[derive(Debug, Clone, Default)]
+pub struct ConsumedPool {
+    pub bundles: Vec<PoolBundle>,
+    pub seq_txs: Vec<PoolTx>,
+    pub price_txs: Vec<PoolTx>,
+    pub committed_txs: usize,
+    seen: BTreeSet<SH256>,
+}
+
+impl ConsumedPool {
+    pub fn add_bundle(&mut self, bundle: &PoolBundle) {
+        self.bundles.push(bundle.clone());
+    }
+
+    pub fn add_tx(&mut self, tx: &PoolTx, source: TraceSource) {
+        if !self.seen.insert(tx.hash) {
+            return;
+        }
+        match source {
+            TraceSource::Seq => self.seq_txs.push(tx.clone()),
+            _ => self.price_txs.push(tx.clone()),
+        }
+    }
+}
+
+impl BuildRecord {
+    pub fn new(
+        payload: &BuildPayload,
+        strategy: BuildStrategy,
+        consumed: ConsumedPool,
+        bid: SU256,
+        block_hash: SH256,
+        state: StateRecord,
+    ) -> Self {
+        let bundles = consumed
+            .bundles
+            .into_iter()
+            .map(|item| BundleRecord {
+                bundle: item.bundle.to_rlp(),
+                dropping: item.dropping.into_iter().collect(),
+            })
+            .collect();
+        Self {
+            strategy,
+            payload: PayloadRecord {
+                round: payload.round,
+                slot: payload.slot,
+                base: payload.base.as_ref().clone(),
+                coinbase: payload.coinbase,
+                gas_limit: payload.gas_limit,
+                timestamp: payload.timestamp,
+                random: payload.random,
+                extra: payload.extra.clone(),
+                withdrawals: payload.withdrawals.clone(),
+                tips_recipient: payload.tips_recipient,
+                top_bid: payload.top_bid,
+            },
+            bundles,
+            seq_txs: consumed.seq_txs.iter().map(|tx| tx.to_rlp()).collect(),
+            price_txs: consumed.price_txs.iter().map(|tx| tx.to_rlp()).collect(),
+            bid: Some(bid),
+            block_hash: Some(block_hash),
+            committed_txs: consumed.committed_txs,
+            state,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(debug)?;
+        serde_json::from_slice(&data).map_err(debug)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let data = serde_json::to_vec(self).map_err(debug)?;
+        std::fs::write(path, data).map_err(debug)
+    }
+
+    pub fn payload(&self) -> BuildPayload {
+        let p = &self.payload;
+        BuildPayload {
+            round: p.round,
+            slot: p.slot,
+            base: Arc::new(p.base.clone()),
+            coinbase: p.coinbase,
+            gas_limit: p.gas_limit,
+            timestamp: p.timestamp,
+            random: p.random,
+            extra: p.extra.clone(),
+            withdrawals: p.withdrawals.clone(),
+            tips_recipient: p.tips_recipient,
+            bid_strategy: self
+                .bid
+                .map(|bid| Arc::new(RecordedBid(bid)) as Arc<dyn BidStrategy>),
+            top_bid: p.top_bid,
+        }
+    }
+
+    pub fn txpool(&self, signer: &Signer) -> Result<TxPool, String> {
+        let pool = TxPool::new(signer.clone(), usize::max_value());
+        for tx in &self.seq_txs {
+            let tx = PoolTx::from_rlp(signer, tx.clone()).map_err(debug)?;
+            pool.seq_pool.push(tx).map_err(debug)?;
+        }
+        for tx in &self.price_txs {
+            let tx = PoolTx::from_rlp(signer, tx.clone()).map_err(debug)?;
+            pool.price_pool.push(tx).map_err(debug)?;
+        }
+        for item in &self.bundles {
+            let bundle = Bundle::from_rlp(signer, item.bundle.clone()).map_err(debug)?;
+            let dropping = item.dropping.iter().cloned().collect();
+            pool.bundle_pool
+                .add(bundle, dropping, &SignedDuration::from_millis(0));
+        }
+        Ok(pool)
+    }
+}
+
+#[derive(Debug)]
+struct RecordedBid(SU256);
+
+impl BidStrategy for RecordedBid {
+    fn bid(&self, _: &BidContext) -> SU256 {
+        self.0
+    }
+}
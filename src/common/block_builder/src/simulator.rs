@@ -2,11 +2,15 @@ use std::prelude::v1::*;
 
 use std::sync::{mpsc, Arc};
 
-use super::BuildEnv;
+use super::{BuildEnv, CallBundleResult, CallBundleTxResult, TraceCallRequest, UnsupportedFork};
 use base::trace::Alive;
+use crypto::keccak_hash;
 use eth_client::ExecutionClient;
-use eth_types::{BlockHeader, PoolTx, Signer, SH160, SU256};
-use evm_executor::{Context, ExecuteError, Executor};
+use eth_types::{BlockHeader, PoolTx, Signer, SH160, SU256, U256};
+use evm_executor::{
+    BlockStateFetcher, ChainSpec, Context, ExecuteError, Executor, Fork, ProofError, StateBackend,
+    StateBackends, StateCoalescer, StateDiff, TraceConfig, TraceResult,
+};
 use statedb::StateDB;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
@@ -19,6 +23,10 @@ pub struct Simulator {
     client: Arc<ExecutionClient>,
     alive: Alive,
     tp: Mutex<ThreadPool>,
+    // shared by the fetchers of the latest block
+    coalescer: Mutex<Option<StateCoalescer>>,
+    backends: StateBackends,
+    verify: bool,
 }
 
 #[derive(Debug)]
@@ -26,6 +34,8 @@ pub struct SimulateResult<T> {
     pub tag: T,
     pub tx: PoolTx,
     pub err: Option<SimulateError>,
+    // what the tx changes, not available if the tx is simulated by the execution node
+    pub state_diff: Option<StateDiff>,
     pub du: Duration,
     // pub network: CounterResult,
 }
@@ -35,10 +45,18 @@ pub enum SimulateError {
     QueueStalled,
     Execute(ExecuteError),
     State(statedb::Error),
+    InvalidProof(ProofError),
     UnexpectedExited,
+    UnsupportedFork(Fork),
     UnknownError,
 }
 
+impl From<UnsupportedFork> for SimulateError {
+    fn from(e: UnsupportedFork) -> Self {
+        Self::UnsupportedFork(e.0)
+    }
+}
+
 impl From<ExecuteError> for SimulateError {
     fn from(e: ExecuteError) -> Self {
         Self::Execute(e)
@@ -47,7 +65,10 @@ impl From<ExecuteError> for SimulateError {
 
 impl From<statedb::Error> for SimulateError {
     fn from(e: statedb::Error) -> Self {
-        Self::State(e)
+        match ProofError::from_state_error(&e) {
+            Some(e) => Self::InvalidProof(e),
+            None => Self::State(e),
+        }
     }
 }
 
@@ -55,11 +76,47 @@ impl Simulator {
     pub fn new(chain_id: SU256, alive: Alive, num: usize, client: Arc<ExecutionClient>) -> Self {
         let env = Arc::new(BuildEnv::new(chain_id));
         let tp = Mutex::new(threadpool::ThreadPool::new(num));
+        let backends = StateBackends::probe(&client, &StateBackend::all());
         Self {
             env,
             alive,
             tp,
             client,
+            coalescer: Mutex::new(None),
+            backends,
+            verify: false,
+        }
+    }
+
+    // verifies the states read by the fetchers with proofs, see BlockStateFetcher::with_verify.
+    // the txs are always executed again by the builder, so it's only needed when the simulate
+    // results (e.g. eth_callBundle) are served to the users.
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    // the fetchers created for the same block share their storage and proof requests
+    pub fn fetcher(&self, header: &BlockHeader) -> BlockStateFetcher {
+        const COALESCE_WINDOW: Duration = Duration::from_millis(2);
+
+        let number = header.number.as_u64();
+        let mut current = self.coalescer.lock().unwrap();
+        let coalescer = match current.as_ref() {
+            Some(coalescer) if coalescer.number() == number => coalescer.clone(),
+            _ => {
+                let coalescer = StateCoalescer::new(self.client.clone(), number, COALESCE_WINDOW);
+                *current = Some(coalescer.clone());
+                coalescer
+            }
+        };
+        let fetcher = BlockStateFetcher::new(self.client.clone(), number.into())
+            .with_coalescer(coalescer)
+            .with_backends(&self.backends)
+            .with_state_root(header.state_root);
+        match self.verify {
+            true => fetcher.with_verify(header.state_root),
+            false => fetcher,
         }
     }
 
@@ -67,22 +124,27 @@ impl Simulator {
         &self.env.signer
     }
 
+    pub fn spec(&self) -> &ChainSpec {
+        &self.env.spec
+    }
+
     fn simulate_inner<S>(
         env: &BuildEnv,
         mut state_db: S,
         header: Arc<BlockHeader>,
         tx: &PoolTx,
-    ) -> Result<PoolTx, SimulateError>
+    ) -> Result<(PoolTx, StateDiff), SimulateError>
     where
         S: StateDB,
     {
         let caller = env.signer.sender(&tx.tx);
+        let rules = env.rules(&header)?;
         let ctx = Context {
             chain_id: &env.signer.chain_id,
             caller: &caller,
-            cfg: &env.cfg,
+            cfg: &rules.cfg,
             tx: &tx,
-            precompile: &env.precompile_set,
+            precompile: &rules.precompile_set,
             header: &header,
         };
 
@@ -90,7 +152,7 @@ impl Simulator {
         let access_list = state_db.export_access_list(Some(&SH160::default()));
         state_db.flush()?;
 
-        Ok(PoolTx {
+        let pooltx = PoolTx {
             caller,
             tx: tx.tx.clone(),
             access_list: Arc::new(access_list),
@@ -99,7 +161,121 @@ impl Simulator {
             allow_revert: true,
             block: state_db.parent().number.as_u64(),
             result: String::from_utf8_lossy(&result.err).into(),
-        })
+        };
+        Ok((pooltx, result.state_diff))
+    }
+
+    // runs the call on top of the state with the geth-compatible tracer
+    pub fn trace_call<S>(
+        &self,
+        mut state_db: S,
+        header: &BlockHeader,
+        req: &TraceCallRequest,
+        cfg: &TraceConfig,
+    ) -> Result<TraceResult, SimulateError>
+    where
+        S: StateDB,
+    {
+        let nonce = state_db.get_nonce(&req.from)?;
+        let tx = req.to_pool_tx(self.env.signer.chain_id, nonce, header);
+        let rules = self.env.rules(header)?;
+        let ctx = Context {
+            chain_id: &self.env.signer.chain_id,
+            caller: &tx.caller,
+            cfg: &rules.cfg,
+            tx: &tx,
+            precompile: &rules.precompile_set,
+            header,
+        };
+        let (_, trace) = Executor::trace(ctx, &mut state_db, cfg)?;
+        Ok(trace)
+    }
+
+    // executes the txs in order on the same state, same as flashbots' eth_callBundle
+    pub fn simulate_bundle<S>(
+        &self,
+        mut state_db: S,
+        header: &BlockHeader,
+        txs: &[PoolTx],
+    ) -> Result<CallBundleResult, SimulateError>
+    where
+        S: StateDB,
+    {
+        let rules = self.env.rules(header)?;
+        let coinbase = header.miner;
+        let mut header = header.clone();
+        let mut out = CallBundleResult {
+            state_block_number: state_db.parent().number,
+            ..Default::default()
+        };
+        let mut hashes = Vec::with_capacity(txs.len() * 32);
+        for tx in txs {
+            hashes.extend_from_slice(tx.hash.as_bytes());
+            let ctx = Context {
+                chain_id: &self.env.signer.chain_id,
+                caller: &tx.caller,
+                cfg: &rules.cfg,
+                tx,
+                precompile: &rules.precompile_set,
+                header: &header,
+            };
+            let balance = state_db.get_balance(&coinbase)?;
+            let result = match Executor::new(ctx, &mut state_db).run(false) {
+                Ok(result) => result,
+                Err(ExecuteError::StateError(err)) => return Err(err.into()),
+                // the invalid tx is reported and skipped, the rest of the bundle still runs
+                Err(err) => {
+                    out.results.push(CallBundleTxResult {
+                        tx_hash: tx.hash,
+                        from_address: tx.caller,
+                        to_address: tx.tx.to(),
+                        error: Some(format!("{:?}", err)),
+                        ..Default::default()
+                    });
+                    continue;
+                }
+            };
+            let coinbase_diff: SU256 = state_db
+                .get_balance(&coinbase)?
+                .saturating_sub(*balance)
+                .into();
+            header.gas_used = header.gas_used + result.used_gas;
+
+            let gas_price = tx
+                .tx
+                .effective_gas_tip(Some(&header.base_fee_per_gas))
+                .unwrap_or_default();
+            let gas_fees = SU256::from(result.used_gas) * gas_price;
+            let (value, error, revert) = match result.success {
+                true => (Some(result.err), None, None),
+                false => (None, Some("execution reverted".into()), Some(result.err)),
+            };
+            out.results.push(CallBundleTxResult {
+                tx_hash: tx.hash,
+                from_address: tx.caller,
+                to_address: tx.tx.to(),
+                gas_used: result.used_gas,
+                gas_price,
+                gas_fees,
+                coinbase_diff,
+                eth_sent_to_coinbase: coinbase_diff.saturating_sub(*gas_fees).into(),
+                value,
+                error,
+                revert,
+                state_diff: result.state_diff.clone(),
+            });
+            out.state_diff.extend(result.state_diff);
+            out.coinbase_diff = out.coinbase_diff + coinbase_diff;
+            out.gas_fees = out.gas_fees + gas_fees;
+            out.total_gas_used = out.total_gas_used + result.used_gas;
+        }
+        out.eth_sent_to_coinbase = out.coinbase_diff.saturating_sub(*out.gas_fees).into();
+        if out.total_gas_used.as_u64() > 0 {
+            let total_gas_used = U256::from(out.total_gas_used.as_u64());
+            out.bundle_gas_price = (*out.coinbase_diff.raw() / total_gas_used).into();
+        }
+        out.bundle_hash = keccak_hash(&hashes).into();
+        Ok(out)
     }
 
     pub fn simulate<'a, I, S>(
@@ -173,6 +349,7 @@ impl Simulator {
                 tag,
                 tx: pooltx.clone(),
                 err: Some(SimulateError::QueueStalled),
+                state_diff: None,
                 du: Duration::from_secs(0),
                 // network: CounterResult::new(),
             });
@@ -193,6 +370,7 @@ impl Simulator {
                         tag,
                         tx: pooltx,
                         err: Some(SimulateError::QueueStalled),
+                        state_diff: None,
                         du: start.elapsed(),
                         // network: CounterResult::new(),
                     });
@@ -219,6 +397,7 @@ impl Simulator {
                                 tag,
                                 tx: pooltx,
                                 err: None,
+                                state_diff: None,
                                 du: start.elapsed(),
                                 // network: CounterResult::new(),
                             });
@@ -232,10 +411,11 @@ impl Simulator {
                 }
                 let _guard = glog::set_tag("dry_run");
                 let response = match Self::simulate_inner(&env, state_db, header, &pooltx) {
-                    Ok(tx) => SimulateResult {
+                    Ok((tx, state_diff)) => SimulateResult {
                         tag,
                         tx,
                         err: None,
+                        state_diff: Some(state_diff),
                         du: start.elapsed(),
                         // network: fetcher.get_miss_usage(),
                     },
@@ -243,6 +423,7 @@ impl Simulator {
                         tag,
                         tx: pooltx,
                         err: Some(err),
+                        state_diff: None,
                         du: start.elapsed(),
                         // network: fetcher.get_miss_usage(),
                     },
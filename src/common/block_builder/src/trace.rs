@@ -0,0 +1,131 @@
+use std::prelude::v1::*;
+
+use super::{BuildStrategy, CommitAction};
+use eth_types::{SH256, SU256};
+use serde::Serialize;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceSource {
+    BundleSimulation,
+    Bundle,
+    Seq,
+    Price,
+    Payment,
+}
+
+impl Default for TraceSource {
+    fn default() -> Self {
+        Self::Price
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TxTrace {
+    pub hash: SH256,
+    pub source: TraceSource,
+    pub bundle: Option<SH256>,
+    pub action: &'static str,
+    pub reason: Option<String>,
+    pub gas: u64,
+    pub tip: SU256,
+    pub elapsed_us: u64,
+    pub state_miss: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleTrace {
+    pub hash: SH256,
+    pub source: TraceSource,
+    pub action: &'static str,
+    pub reason: Option<String>,
+    pub gas: u64,
+    pub profit: SU256,
+    pub refund: SU256,
+    pub elapsed_us: u64,
+    pub state_miss: u64,
+}
+
+// records every commit decision of a build, so we can find out why a tx was not included.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BuildTrace {
+    pub slot: u64,
+    pub number: u64,
+    pub round: usize,
+    pub strategy: BuildStrategy,
+    // the value the bid strategy decided to pay to the proposer
+    pub bid: SU256,
+    pub txs: Vec<TxTrace>,
+    pub bundles: Vec<BundleTrace>,
+
+    // where the next committed txs come from
+    #[serde(skip)]
+    pub source: TraceSource,
+    #[serde(skip)]
+    pub bundle: Option<SH256>,
+}
+
+impl BuildTrace {
+    pub fn record_tx(
+        &mut self,
+        hash: SH256,
+        action: &CommitAction,
+        gas: u64,
+        tip: SU256,
+        elapsed: Duration,
+        state_miss: u64,
+    ) {
+        let (action, reason) = match action {
+            CommitAction::Success(receipt) => {
+                ("success", (!receipt.succ()).then(|| "reverted".into()))
+            }
+            CommitAction::Shift => ("shift", None),
+            CommitAction::Pop(reason) => ("pop", Some(reason.clone())),
+            CommitAction::MarkFail(reason) => ("mark_fail", Some(reason.clone())),
+            CommitAction::Stop(reason) => ("stop", Some(reason.clone())),
+            CommitAction::RemoveTx => ("remove_tx", None),
+        };
+        self.txs.push(TxTrace {
+            hash,
+            source: self.source,
+            bundle: self.bundle,
+            action,
+            reason,
+            gas,
+            tip,
+            elapsed_us: elapsed.as_micros() as u64,
+            state_miss,
+        });
+    }
+
+    pub fn record_bundle(
+        &mut self,
+        hash: SH256,
+        action: &'static str,
+        reason: Option<String>,
+        gas: u64,
+        profit: SU256,
+        refund: SU256,
+        elapsed: Duration,
+    ) {
+        let state_miss = self
+            .txs
+            .iter()
+            .rev()
+            .take_while(|n| n.bundle == Some(hash) && n.source == self.source)
+            .map(|n| n.state_miss)
+            .sum();
+        self.bundles.push(BundleTrace {
+            hash,
+            source: self.source,
+            action,
+            reason,
+            gas,
+            profit,
+            refund,
+            elapsed_us: elapsed.as_micros() as u64,
+            state_miss,
+        });
+    }
+}
@@ -3,18 +3,21 @@ use std::prelude::v1::*;
 use std::collections::BTreeMap;
 use std::sync::Arc;
 
-use base::format::parse_ether;
-use base::serde::deserialize_ether;
 use base::trace::ItemIndexer;
 use crypto::Secp256k1PrivateKey;
 use eth_types::{
-    Block, BlockHeader, Bundle, HexBytes, Receipt, Signer, TransactionInner, Withdrawal, SH160,
-    SH256, SU256, SU64, U256,
+    Block, BlockHeader, Bundle, DynamicFeeTx, HexBytes, PoolTx, Receipt, Signer, TransactionInner,
+    Withdrawal, SH160, SH256, SU256, SU64, U256,
 };
-use evm_executor::PrecompileSet;
+use evm_executor::{ChainSpec, Fork, ForkRules, ProofError, StateBackend, StateDiff};
+use mev_boost::BidStrategy;
+pub use mev_boost::{TipsStrategy, TipsStrategyRule};
 use statedb::{StateDB, StateFetcher, TrieMemStore};
+use txpool::TxPool;
 
-use serde::Deserialize;
+use super::{BuildTrace, ConsumedPool};
+
+use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
@@ -26,6 +29,36 @@ pub struct Config {
     pub payer_sk: Secp256k1PrivateKey,
 
     pub extra: String,
+
+    // the ordering strategies built in parallel, only the default one if it's empty
+    #[serde(default)]
+    pub strategies: Vec<BuildStrategy>,
+
+    #[serde(default)]
+    pub payment: PaymentConfig,
+
+    // saves the input of every build into this directory, so it can be replayed offline
+    #[serde(default)]
+    pub record_dir: Option<String>,
+
+    // keeps the fetched states across blocks, the max number of the cached entries, 0 disables it
+    #[serde(default)]
+    pub state_cache_size: usize,
+
+    // doesn't trust the execution node, verifies every fetched state with merkle proofs against
+    // the state root of the parent block
+    #[serde(default)]
+    pub verify_state: bool,
+
+    // the backends allowed to read the states with, the ones the execution node doesn't support
+    // are dropped at startup
+    #[serde(default = "StateBackend::all")]
+    pub state_backends: Vec<StateBackend>,
+
+    // learns the storage slots read by each (contract, selector) and prefetches them for the txs
+    // without an access list, the max number of the learned pairs, 0 disables it
+    #[serde(default)]
+    pub slot_predictor_size: usize,
 }
 
 impl Config {
@@ -34,18 +67,119 @@ impl Config {
     }
 }
 
+// the txs paid by the payer, e.g. the proposer tips and the bundle refunds
+#[derive(Deserialize, Debug, Clone)]
+pub struct PaymentConfig {
+    // sends EIP-1559 txs instead of legacy ones
+    #[serde(default)]
+    pub dynamic_fee: bool,
+    #[serde(default = "PaymentConfig::default_gas_cap")]
+    pub gas_cap: u64,
+    // overrides the `gas_cap` for specific recipients
+    #[serde(default)]
+    pub gas_caps: BTreeMap<SH160, u64>,
+}
+
+impl Default for PaymentConfig {
+    fn default() -> Self {
+        Self {
+            dynamic_fee: false,
+            gas_cap: Self::default_gas_cap(),
+            gas_caps: BTreeMap::new(),
+        }
+    }
+}
+
+impl PaymentConfig {
+    pub fn default_gas_cap() -> u64 {
+        100000
+    }
+
+    pub fn gas_cap(&self, recipient: &SH160) -> u64 {
+        match self.gas_caps.get(recipient) {
+            Some(cap) => *cap,
+            None => self.gas_cap,
+        }
+    }
+}
+
 pub struct BuildEnv {
-    pub precompile_set: PrecompileSet,
     pub signer: Signer,
-    pub cfg: evm_executor::Config,
+    pub spec: ChainSpec,
+    rules: BTreeMap<Fork, ForkRules>,
 }
 
 impl BuildEnv {
     pub fn new(chain_id: SU256) -> Self {
+        let rules = Fork::all()
+            .into_iter()
+            .filter_map(|fork| Some((fork, ForkRules::new(fork)?)))
+            .collect();
         Self {
-            precompile_set: evm_executor::PrecompileSet::berlin(),
             signer: Signer::new(chain_id),
-            cfg: evm_executor::Config::shanghai(),
+            spec: ChainSpec::by_chain_id(chain_id.as_u64()),
+            rules,
+        }
+    }
+
+    pub fn rules(&self, header: &BlockHeader) -> Result<&ForkRules, UnsupportedFork> {
+        let fork = self.spec.header_fork(header);
+        self.rules.get(&fork).ok_or(UnsupportedFork(fork))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct UnsupportedFork(pub Fork);
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildStrategy {
+    // bundles, then seq pool, then price pool
+    BundlesFirst,
+    // no bundles, seq pool and price pool ordered by effective tip only, a seq tx doesn't
+    // replace a price tx of the same nonce which pays more
+    PriceOnly,
+    // seq pool, then bundles, then price pool
+    SeqFirst,
+    // bundles, then seq pool and price pool ordered by effective tip together
+    InterleavedByTip,
+}
+
+impl Default for BuildStrategy {
+    fn default() -> Self {
+        Self::BundlesFirst
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StrategyResult {
+    pub strategy: BuildStrategy,
+    pub profit: SU256,
+    pub gas_used: u64,
+    pub txs: usize,
+    pub err: Option<String>,
+}
+
+impl StrategyResult {
+    pub fn from_result(
+        strategy: BuildStrategy,
+        result: &Result<&BlockResult, &BuildError>,
+    ) -> Self {
+        match result {
+            Ok(result) => Self {
+                strategy,
+                profit: result.profit,
+                gas_used: result.block.header.gas_used.as_u64(),
+                txs: result.block.transactions.len(),
+                err: None,
+            },
+            Err(err) => Self {
+                strategy,
+                profit: SU256::zero(),
+                gas_used: 0,
+                txs: 0,
+                err: Some(format!("{:?}", err)),
+            },
         }
     }
 }
@@ -58,10 +192,33 @@ pub struct BuildResult {
     pub profit: SU256,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BundleResult {
     pub bundle: Arc<Bundle>,
     pub status: String,
+    pub profit: SU256,
+    pub refund: SU256,
+}
+
+impl BundleResult {
+    pub fn reverted(bundle: Arc<Bundle>, status: String) -> Self {
+        Self {
+            bundle,
+            status,
+            profit: SU256::zero(),
+            refund: SU256::zero(),
+        }
+    }
+
+    // the profit is what the coinbase kept after the refund is sent back
+    pub fn submitted(bundle: Arc<Bundle>, profit: SU256, refund: SU256) -> Self {
+        Self {
+            bundle,
+            status: "submitted".into(),
+            profit: profit.saturating_sub(*refund).into(),
+            refund,
+        }
+    }
 }
 
 pub struct BuildPayload {
@@ -75,10 +232,13 @@ pub struct BuildPayload {
     pub extra: HexBytes,
     pub withdrawals: Vec<Withdrawal>,
     pub tips_recipient: Option<SH160>,
+    // overrides the `tips_strategy` in the config
+    pub bid_strategy: Option<Arc<dyn BidStrategy>>,
+    pub top_bid: Option<SU256>,
 }
 
 impl BuildPayload {
-    pub fn next_block(&self) -> BlockHeader {
+    pub fn next_block(&self, spec: &ChainSpec) -> BlockHeader {
         let gas_limit =
             Self::calc_gas_limit(self.base.gas_limit.as_u64(), self.gas_limit.as_u64()).into();
         let base_fee = Self::calc_base_fee(
@@ -86,9 +246,11 @@ impl BuildPayload {
             self.base.gas_used.as_u64(),
             self.base.base_fee_per_gas.raw().clone(),
         );
+        let number = self.base.number + SU64::from(1);
+        let cancun = spec.fork(number.as_u64(), self.timestamp) >= Fork::Cancun;
         BlockHeader {
             parent_hash: self.base.hash(),
-            number: self.base.number + SU64::from(1),
+            number,
             gas_limit,
             timestamp: self.timestamp.into(),
             miner: self.coinbase,
@@ -96,6 +258,13 @@ impl BuildPayload {
             extra_data: self.extra.clone(),
             base_fee_per_gas: base_fee,
             difficulty: 0.into(),
+            // the txs we build never carry blobs
+            blob_gas_used: cancun.then(|| 0.into()),
+            excess_blob_gas: cancun.then(|| {
+                let excess = self.base.excess_blob_gas.unwrap_or_default();
+                let used = self.base.blob_gas_used.unwrap_or_default();
+                evm_executor::calc_excess_blob_gas(excess.as_u64(), used.as_u64()).into()
+            }),
             ..Default::default()
         }
     }
@@ -160,89 +329,30 @@ impl BuildPayload {
 pub enum BuildError {
     NoTx,
     StateError(statedb::Error),
+    // the execution node served a state which doesn't match the proof
+    InvalidProof(ProofError),
     SendTipsFail(String),
+    SendRefundFail(String),
     FeeTooLow,
+    UnsupportedFork(Fork),
     InternalError(String),
 }
 
 impl From<statedb::Error> for BuildError {
     fn from(err: statedb::Error) -> Self {
-        Self::StateError(err)
+        match ProofError::from_state_error(&err) {
+            Some(err) => Self::InvalidProof(err),
+            None => Self::StateError(err),
+        }
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Default, Eq, PartialEq)]
-pub struct TipsStrategy {
-    #[serde(deserialize_with = "deserialize_ether")]
-    pub max: SU256,
-    pub bundle: Option<TipsStrategyRule>,
-    pub internal: Option<TipsStrategyRule>,
-    pub normal: Option<TipsStrategyRule>,
-}
-
-impl TipsStrategy {
-    pub fn get(&self, tag: &str, reward: SU256, internals: &[u64], bundles: usize) -> SU256 {
-        let bundle = if let Some(bundle) = &self.bundle {
-            if bundles > 0 {
-                bundle.start
-            } else {
-                SU256::zero()
-            }
-        } else {
-            SU256::zero()
-        };
-
-        let internal = if let Some(strategy) = &self.internal {
-            let mut total = SU256::zero();
-            if let Some(max_blk_miss) = internals.iter().max() {
-                total += strategy.start;
-                total += (SU256::from(internals.len() as u64) * strategy.num_increment)
-                    .min(strategy.max_num_increment);
-                total += (SU256::from(*max_blk_miss) * strategy.blocks_increment)
-                    .min(strategy.max_blocks_increment);
-            }
-            total
-        } else {
-            SU256::zero()
-        };
-
-        let normal = if let Some(strategy) = &self.normal {
-            strategy.start
-        } else {
-            SU256::zero()
-        };
-
-        let profit = reward + internal.max(bundle).max(normal).min(self.max);
-
-        glog::info!(
-            "[{}][profit={}] details: bundle({})={}, internal({:?})={}, normal={}, reward={}",
-            tag,
-            parse_ether(&profit, 18),
-            bundles,
-            parse_ether(&bundle, 18),
-            internals,
-            parse_ether(&internal, 18),
-            parse_ether(&normal, 18),
-            parse_ether(&reward, 18),
-        );
-        profit
+impl From<UnsupportedFork> for BuildError {
+    fn from(err: UnsupportedFork) -> Self {
+        Self::UnsupportedFork(err.0)
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Default, Eq, PartialEq)]
-pub struct TipsStrategyRule {
-    #[serde(deserialize_with = "deserialize_ether")]
-    pub start: SU256,
-    #[serde(deserialize_with = "deserialize_ether")]
-    pub num_increment: SU256,
-    #[serde(deserialize_with = "deserialize_ether")]
-    pub max_num_increment: SU256,
-    #[serde(deserialize_with = "deserialize_ether")]
-    pub blocks_increment: SU256,
-    #[serde(deserialize_with = "deserialize_ether")]
-    pub max_blocks_increment: SU256,
-}
-
 pub struct Environment<F: StateFetcher, D: StateDB> {
     pub state: D,
     pub miner_balance: SU256,
@@ -250,12 +360,20 @@ pub struct Environment<F: StateFetcher, D: StateDB> {
     pub txs: Vec<Arc<TransactionInner>>,
     pub receipts: Vec<Receipt>,
     pub gas_pool: u64,
+    // the gas kept out of the gas pool for the tips tx
+    pub payment_gas: u64,
     pub checked_txs: BTreeMap<SH256, bool>,
     pub tips_recipient: Option<SH160>,
     pub fetcher: F,
     pub round: usize,
     pub callers: ItemIndexer<SH160>,
     pub store: TrieMemStore,
+    pub trace: BuildTrace,
+    pub pool_updates: Vec<PoolUpdate>,
+    // the replay stops at the recorded tx count instead of the deadline
+    pub tx_limit: Option<usize>,
+    // set when the build is recorded
+    pub consumed: Option<ConsumedPool>,
 }
 
 impl<F: StateFetcher, D: StateDB> Environment<F, D> {
@@ -274,12 +392,24 @@ impl<F: StateFetcher, D: StateDB> Environment<F, D> {
             txs: Vec::new(),
             receipts: Vec::new(),
             gas_pool: Default::default(),
+            payment_gas: 0,
             miner_balance: SU256::zero(),
             checked_txs: BTreeMap::new(),
             tips_recipient,
             round,
             callers: ItemIndexer::new(),
             store,
+            trace: BuildTrace::default(),
+            pool_updates: Vec::new(),
+            tx_limit: None,
+            consumed: None,
+        }
+    }
+
+    pub fn reached_limit(&self) -> bool {
+        match self.tx_limit {
+            Some(limit) => self.txs.len() >= limit,
+            None => false,
         }
     }
 
@@ -294,10 +424,11 @@ impl<F: StateFetcher, D: StateDB> Environment<F, D> {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct FillResult {
     pub bundle_result: Vec<BundleResult>,
     pub internal_txs: Vec<u64>,
+    pub refunds: SU256,
     pub profit: SU256,
 }
 
@@ -313,9 +444,206 @@ pub enum CommitAction<'a> {
 
 pub struct BlockResult {
     pub slot: u64,
+    pub strategy: BuildStrategy,
+    pub strategies: Vec<StrategyResult>,
     pub block: Block,
     pub bundles: Vec<BundleResult>,
     pub internal_txs: Vec<u64>,
     pub receipts: Vec<Receipt>,
     pub profit: SU256,
+    pub trace: BuildTrace,
+    pub pool_updates: Vec<PoolUpdate>,
+    pub consumed: Option<ConsumedPool>,
+}
+
+impl BlockResult {
+    pub fn apply_pool_updates(&self, txpool: &TxPool) {
+        for update in &self.pool_updates {
+            update.apply(txpool);
+        }
+    }
+}
+
+// the pool changes found while building, they are applied once the block is picked, so the
+// strategies running in parallel see the same pools.
+#[derive(Debug, Clone)]
+pub enum PoolUpdate {
+    Remove(SH256),
+    MarkFail(SH256, String),
+}
+
+impl PoolUpdate {
+    pub fn apply(&self, txpool: &TxPool) {
+        match self {
+            Self::Remove(hash) => {
+                txpool.seq_pool.remove(hash);
+                txpool.price_pool.remove(hash);
+            }
+            Self::MarkFail(hash, reason) => txpool.seq_pool.mark_fail(hash, reason.clone()),
+        }
+    }
+}
+
+// the result of re-executing a block locally, see `BlockBuilder::validate_block`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BlockValidation {
+    pub number: u64,
+    pub hash: SH256,
+    // the header fields which differ from the re-executed block
+    pub mismatches: Vec<FieldMismatch>,
+    pub diverging_tx: Option<DivergingTx>,
+}
+
+impl BlockValidation {
+    pub fn is_valid(&self) -> bool {
+        self.mismatches.is_empty() && self.diverging_tx.is_none()
+    }
+
+    pub fn check<T: PartialEq + std::fmt::Debug>(&mut self, field: &'static str, want: T, got: T) {
+        if want != got {
+            self.mismatches.push(FieldMismatch {
+                field,
+                want: format!("{:?}", want),
+                got: format!("{:?}", got),
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldMismatch {
+    pub field: &'static str,
+    pub want: String,
+    pub got: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DivergingTx {
+    pub index: u64,
+    pub hash: SH256,
+    pub reason: String,
+}
+
+// the call object of debug_traceCall
+#[derive(Deserialize, Debug, Clone)]
+pub struct TraceCallRequest {
+    pub from: SH160,
+    pub to: Option<SH160>,
+    pub gas: Option<SU64>,
+    #[serde(default)]
+    pub value: SU256,
+    #[serde(default, alias = "data")]
+    pub input: HexBytes,
+    pub nonce: Option<SU64>,
+}
+
+impl TraceCallRequest {
+    // the tx is not signed, the caller is taken from `from` directly
+    pub fn to_pool_tx(&self, chain_id: SU256, nonce: u64, header: &BlockHeader) -> PoolTx {
+        let tx = TransactionInner::DynamicFee(DynamicFeeTx {
+            chain_id,
+            nonce: self.nonce.unwrap_or(nonce.into()),
+            gas: self.gas.unwrap_or(header.gas_limit),
+            to: self.to.into(),
+            value: self.value,
+            data: self.input.clone(),
+            ..Default::default()
+        });
+        PoolTx {
+            caller: self.from,
+            hash: tx.hash(),
+            tx,
+            access_list: Arc::new(Vec::new()),
+            gas: 0,
+            allow_revert: true,
+            block: header.number.as_u64(),
+            result: String::new(),
+        }
+    }
+}
+
+// the params of eth_callBundle, the bundle is executed on top of `state_block_number`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CallBundleRequest {
+    pub txs: Vec<HexBytes>,
+    pub block_number: SU64,
+    pub state_block_number: String,
+    pub timestamp: Option<SU64>,
+    pub coinbase: Option<SH160>,
+    pub base_fee: Option<SU256>,
+}
+
+impl CallBundleRequest {
+    // the header of the block which the bundle is simulated in
+    pub fn header(&self, parent: &BlockHeader, spec: &ChainSpec) -> BlockHeader {
+        let timestamp = match self.timestamp {
+            Some(timestamp) => timestamp,
+            None => parent.timestamp + SU64::from(12),
+        };
+        let base_fee = match self.base_fee {
+            Some(base_fee) => base_fee,
+            None => BuildPayload::calc_base_fee(
+                parent.gas_limit.as_u64(),
+                parent.gas_used.as_u64(),
+                parent.base_fee_per_gas.raw().clone(),
+            ),
+        };
+        let cancun = spec.fork(self.block_number.as_u64(), timestamp.as_u64()) >= Fork::Cancun;
+        BlockHeader {
+            parent_hash: parent.hash(),
+            number: self.block_number,
+            gas_limit: parent.gas_limit,
+            timestamp,
+            miner: self.coinbase.unwrap_or(parent.miner),
+            mix_hash: parent.mix_hash,
+            base_fee_per_gas: base_fee,
+            difficulty: 0.into(),
+            blob_gas_used: cancun.then(|| 0.into()),
+            excess_blob_gas: cancun.then(|| {
+                let excess = parent.excess_blob_gas.unwrap_or_default();
+                let used = parent.blob_gas_used.unwrap_or_default();
+                evm_executor::calc_excess_blob_gas(excess.as_u64(), used.as_u64()).into()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+// the result of eth_callBundle, same as flashbots
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CallBundleResult {
+    pub bundle_hash: SH256,
+    pub bundle_gas_price: SU256,
+    pub coinbase_diff: SU256,
+    pub eth_sent_to_coinbase: SU256,
+    pub gas_fees: SU256,
+    pub results: Vec<CallBundleTxResult>,
+    pub state_block_number: SU64,
+    pub total_gas_used: SU64,
+    // the changes of the whole bundle
+    pub state_diff: StateDiff,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CallBundleTxResult {
+    pub tx_hash: SH256,
+    pub from_address: SH160,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to_address: Option<SH160>,
+    pub gas_used: SU64,
+    // the effective tip paid to the coinbase
+    pub gas_price: SU256,
+    pub gas_fees: SU256,
+    pub coinbase_diff: SU256,
+    pub eth_sent_to_coinbase: SU256,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<HexBytes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert: Option<HexBytes>,
+    pub state_diff: StateDiff,
 }
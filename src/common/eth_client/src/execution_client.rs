@@ -7,6 +7,7 @@ use eth_types::{
 };
 use jsonrpc::{JsonrpcClient, MixRpcClient, RpcClient, RpcError};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone)]
 pub struct ExecutionClient<C: RpcClient = MixRpcClient> {
@@ -236,6 +237,47 @@ impl<C: RpcClient> ExecutionClient<C> {
         let hashes = hashes.iter().map(|n| [n]).collect::<Vec<_>>();
         self.client.batch_rpc("eth_getTransactionReceipt", &hashes)
     }
+
+    // the accounts and slots changed by the txs of the block, from the prestateTracer in diff mode
+    pub fn trace_block_state_diff(
+        &self,
+        selector: BlockSelector,
+    ) -> Result<Vec<TxStateDiff>, RpcError> {
+        let cfg = serde_json::json!({
+            "tracer": "prestateTracer",
+            "tracerConfig": { "diffMode": true },
+        });
+        match selector {
+            BlockSelector::Hash(hash) => self.client.rpc("debug_traceBlockByHash", (&hash, cfg)),
+            BlockSelector::Number(number) => {
+                self.client.rpc("debug_traceBlockByNumber", (&number, cfg))
+            }
+            BlockSelector::Latest => self.client.rpc("debug_traceBlockByNumber", ("latest", cfg)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TxStateDiff {
+    #[serde(default)]
+    pub tx_hash: Option<SH256>,
+    pub result: PrestateDiff,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PrestateDiff {
+    #[serde(default)]
+    pub pre: BTreeMap<SH160, PrestateAccount>,
+    #[serde(default)]
+    pub post: BTreeMap<SH160, PrestateAccount>,
+}
+
+// only the changed slots are needed, the other fields are ignored
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PrestateAccount {
+    #[serde(default)]
+    pub storage: BTreeMap<SH256, SH256>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default, Eq, PartialEq)]
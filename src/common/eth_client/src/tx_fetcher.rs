@@ -2,9 +2,9 @@ use std::{prelude::v1::*, sync::Mutex};
 
 use base::thread::spawn;
 use base::trace::{Alive, Counter};
-use eth_types::{Bundle, PoolTx, Signer};
+use eth_types::{Bundle, PoolTx, Signer, SH256};
 use jsonrpc::{JsonrpcWsClient, WsClientConfig};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::mpsc;
 use std::time::Duration;
 
@@ -14,14 +14,15 @@ use crate::HashPool;
 pub enum MempoolItem {
     Seq(PoolTx),
     Price(PoolTx),
-    Bundle(Bundle),
+    // the bundle and the txs which can be dropped from it
+    Bundle(Bundle, BTreeSet<SH256>),
 }
 
 impl MempoolItem {
     pub fn pool_tx(&self) -> Option<&PoolTx> {
         match self {
             MempoolItem::Seq(tx) | MempoolItem::Price(tx) => Some(tx),
-            MempoolItem::Bundle(_) => None,
+            MempoolItem::Bundle(..) => None,
         }
     }
 }
@@ -0,0 +1,197 @@
+use std::prelude::v1::*;
+
+use super::{Config, PrecompileSet};
+use eth_types::BlockHeader;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(rename_all = "snake_case")]
+pub enum Fork {
+    London,
+    Shanghai,
+    Cancun,
+    Prague,
+}
+
+impl Fork {
+    pub fn all() -> Vec<Self> {
+        vec![Self::London, Self::Shanghai, Self::Cancun, Self::Prague]
+    }
+}
+
+// the activation of the forks, the blocks before london are not supported.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ChainSpec {
+    pub london_block: u64,
+    pub shanghai_time: Option<u64>,
+    pub cancun_time: Option<u64>,
+    pub prague_time: Option<u64>,
+}
+
+impl Default for ChainSpec {
+    // a dev chain, activates shanghai at genesis, the later forks are not scheduled
+    fn default() -> Self {
+        Self {
+            london_block: 0,
+            shanghai_time: Some(0),
+            cancun_time: None,
+            prague_time: None,
+        }
+    }
+}
+
+impl ChainSpec {
+    pub fn mainnet() -> Self {
+        Self {
+            london_block: 12965000,
+            shanghai_time: Some(1681338455),
+            cancun_time: Some(1710338135),
+            prague_time: Some(1746612311),
+        }
+    }
+
+    pub fn sepolia() -> Self {
+        Self {
+            london_block: 1735371,
+            shanghai_time: Some(1677557088),
+            cancun_time: Some(1706655072),
+            prague_time: Some(1741159776),
+        }
+    }
+
+    pub fn holesky() -> Self {
+        Self {
+            london_block: 0,
+            shanghai_time: Some(1696000704),
+            cancun_time: Some(1707305664),
+            prague_time: Some(1740434112),
+        }
+    }
+
+    pub fn goerli() -> Self {
+        Self {
+            london_block: 5062605,
+            shanghai_time: Some(1678832736),
+            cancun_time: Some(1705473120),
+            prague_time: None,
+        }
+    }
+
+    pub fn by_chain_id(chain_id: u64) -> Self {
+        match chain_id {
+            1 => Self::mainnet(),
+            5 => Self::goerli(),
+            17000 => Self::holesky(),
+            11155111 => Self::sepolia(),
+            _ => Self::default(),
+        }
+    }
+
+    pub fn fork(&self, number: u64, timestamp: u64) -> Fork {
+        let active = |time: Option<u64>| matches!(time, Some(time) if timestamp >= time);
+        if active(self.prague_time) {
+            Fork::Prague
+        } else if active(self.cancun_time) {
+            Fork::Cancun
+        } else if active(self.shanghai_time) {
+            Fork::Shanghai
+        } else {
+            if number < self.london_block {
+                glog::warn!("block #{} is before london, treated as london", number);
+            }
+            Fork::London
+        }
+    }
+
+    pub fn header_fork(&self, header: &BlockHeader) -> Fork {
+        self.fork(header.number.as_u64(), header.timestamp.as_u64())
+    }
+}
+
+// the evm config and precompiles used by the blocks in a fork
+#[derive(Debug)]
+pub struct ForkRules {
+    pub fork: Fork,
+    pub cfg: Config,
+    pub precompile_set: PrecompileSet,
+}
+
+impl ForkRules {
+    // returns None if the evm can't execute the blocks of the fork
+    pub fn new(fork: Fork) -> Option<Self> {
+        let (cfg, precompile_set) = match fork {
+            Fork::London => (Config::london(), PrecompileSet::berlin()),
+            Fork::Shanghai => (Config::shanghai(), PrecompileSet::berlin()),
+            // the evm doesn't have the opcodes of cancun (EIP-1153, EIP-5656, EIP-4844 and
+            // EIP-7516) and the selfdestruct of EIP-6780 yet, so the interpreter stays on
+            // shanghai and the txs using them fail with an invalid opcode.
+            Fork::Cancun => (Config::shanghai(), PrecompileSet::cancun()),
+            Fork::Prague => (Config::shanghai(), PrecompileSet::prague()),
+        };
+        Some(Self {
+            fork,
+            cfg,
+            precompile_set,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fork_boundaries() {
+        glog::init_test();
+        let spec = ChainSpec::mainnet();
+        let shanghai = spec.shanghai_time.unwrap();
+        let cancun = spec.cancun_time.unwrap();
+        let prague = spec.prague_time.unwrap();
+        let number = spec.london_block;
+
+        assert_eq!(spec.fork(number, 0), Fork::London);
+        assert_eq!(spec.fork(number, shanghai - 1), Fork::London);
+        assert_eq!(spec.fork(number, shanghai), Fork::Shanghai);
+        assert_eq!(spec.fork(number, cancun - 1), Fork::Shanghai);
+        assert_eq!(spec.fork(number, cancun), Fork::Cancun);
+        assert_eq!(spec.fork(number, prague - 1), Fork::Cancun);
+        assert_eq!(spec.fork(number, prague), Fork::Prague);
+        assert_eq!(spec.fork(number, u64::max_value()), Fork::Prague);
+        // the blocks before london are treated as london
+        assert_eq!(spec.fork(0, 0), Fork::London);
+    }
+
+    #[test]
+    fn test_unscheduled_forks() {
+        glog::init_test();
+        let spec = ChainSpec::default();
+        assert_eq!(spec.fork(0, 0), Fork::Shanghai);
+        assert_eq!(spec.fork(0, u64::max_value()), Fork::Shanghai);
+
+        let spec = ChainSpec {
+            london_block: 0,
+            shanghai_time: None,
+            cancun_time: None,
+            prague_time: None,
+        };
+        assert_eq!(spec.fork(100, u64::max_value()), Fork::London);
+    }
+
+    #[test]
+    fn test_fork_rules() {
+        for fork in Fork::all() {
+            assert_eq!(ForkRules::new(fork).unwrap().fork, fork);
+        }
+
+        let addrs = |fork| {
+            ForkRules::new(fork)
+                .unwrap()
+                .precompile_set
+                .get_addresses()
+                .len()
+        };
+        assert_eq!(addrs(Fork::Shanghai), 9);
+        assert_eq!(addrs(Fork::Cancun), 10);
+        assert_eq!(addrs(Fork::Prague), 17);
+    }
+}
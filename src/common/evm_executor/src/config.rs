@@ -0,0 +1,13 @@
+use std::prelude::v1::*;
+
+pub use evm::Config;
+
+pub const BLOB_GAS_PER_BLOB: u64 = 1 << 17;
+pub const TARGET_BLOB_GAS_PER_BLOCK: u64 = 3 * BLOB_GAS_PER_BLOB;
+
+// EIP-3860
+pub const INIT_CODE_WORD_GAS: u64 = 2;
+
+pub fn calc_excess_blob_gas(parent_excess_blob_gas: u64, parent_blob_gas_used: u64) -> u64 {
+    (parent_excess_blob_gas + parent_blob_gas_used).saturating_sub(TARGET_BLOB_GAS_PER_BLOCK)
+}
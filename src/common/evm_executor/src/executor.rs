@@ -1,11 +1,16 @@
 use std::prelude::v1::*;
 
-use super::{Context, StateProxy};
+use super::{
+    Config, Context, SlotPredictor, StateDiff, StateProxy, TraceConfig, TraceResult, Tracer,
+    INIT_CODE_WORD_GAS,
+};
 use base::format::parse_ether;
-use eth_types::{HexBytes, Log, Receipt, SH256, SU256, SU64};
+use crypto::keccak_hash;
+use eth_types::{HexBytes, Log, Receipt, TransactionAccessTuple, H160, SH160, SH256, SU256, SU64};
 use statedb::StateDB;
 
 use std::cmp::Ordering;
+use std::collections::BTreeSet;
 use std::time::Instant;
 
 use evm::backend::Apply;
@@ -19,6 +24,7 @@ pub struct Executor<'a, D: StateDB> {
     initial_gas: u64,
     gas: u64,
     gas_price: SU256,
+    tracer: Option<Tracer>,
 }
 
 impl<'a, D: StateDB> Executor<'a, D> {
@@ -30,6 +36,7 @@ impl<'a, D: StateDB> Executor<'a, D> {
             gas: 0,
             initial_gas: 0,
             gas_price,
+            tracer: None,
         }
     }
 
@@ -37,15 +44,50 @@ impl<'a, D: StateDB> Executor<'a, D> {
         Executor::new(ctx, state_db).run(true)
     }
 
+    // dry runs the tx with the geth-compatible tracer
+    pub fn trace(
+        ctx: Context<'a>,
+        state_db: &'a mut D,
+        cfg: &TraceConfig,
+    ) -> Result<(ExecuteResult, TraceResult), ExecuteError> {
+        let mut executor = Executor::new(ctx.clone(), state_db);
+        executor.tracer = Some(Tracer::new(cfg, &ctx));
+        let result = executor.run(true)?;
+        let tracer = executor.tracer.take().unwrap();
+        let trace = tracer.finish(executor.state_db, &result)?;
+        Ok((result, trace))
+    }
+
     pub fn apply(
         ctx: Context<'a>,
         state_db: &'a mut D,
         tx_idx: u64,
+    ) -> Result<Receipt, ExecuteError> {
+        Self::apply_with(ctx, state_db, tx_idx, None)
+    }
+
+    // same as apply, the predictor learns the storage slots read by the tx
+    pub fn apply_with(
+        ctx: Context<'a>,
+        state_db: &'a mut D,
+        tx_idx: u64,
+        predictor: Option<&SlotPredictor>,
     ) -> Result<Receipt, ExecuteError> {
         let mut result = Executor::new(ctx.clone(), state_db).run(false)?;
+        if let Some(predictor) = predictor {
+            // the txs without an access list are prefetched with the predicted slots
+            let prefetched = ctx.tx.access_list.is_empty();
+            predictor.learn(&ctx.tx.tx, &result.storage_reads, prefetched);
+        }
         for log in &mut result.logs {
             log.transaction_hash = ctx.tx.hash;
+            log.transaction_index = tx_idx.into();
         }
+        // the address is set even if the deployment failed, same as geth
+        let contract_address = match ctx.tx.tx.to() {
+            Some(_) => None,
+            None => Some(create_address(ctx.caller, ctx.tx.tx.nonce())),
+        };
         let mut receipt = Receipt {
             status: (result.success as u64).into(),
             transaction_hash: ctx.tx.tx.hash(),
@@ -55,7 +97,7 @@ impl<'a, D: StateDB> Executor<'a, D> {
             cumulative_gas_used: ctx.header.gas_used + result.used_gas,
             logs: result.logs,
             logs_bloom: HexBytes::new(),
-            contract_address: None, // the rlp didn't included;
+            contract_address, // the rlp didn't included;
             root: None,
             block_hash: None,
             block_number: None,
@@ -74,6 +116,8 @@ impl<'a, D: StateDB> Executor<'a, D> {
         let mut base_fee = self.ctx.header.base_fee_per_gas;
         let gas_fee_cap = tx.max_fee_per_gas();
 
+        // the rules which don't depend on the state
+        self.validate()?;
         self.check_nonce(true, dry_run)?;
 
         if &base_fee > gas_fee_cap {
@@ -110,9 +154,18 @@ impl<'a, D: StateDB> Executor<'a, D> {
         // glog::info!("finish prefetch");
 
         self.check_nonce(false, dry_run)?;
+        if let Some(tracer) = &mut self.tracer {
+            tracer.capture_start(self.state_db)?;
+        }
+        let caller_balance = self.state_db.get_balance(self.ctx.caller)?;
         self.buy_gas(dry_run)?;
 
         let mut result = ExecuteResult::default();
+        // recorded first so the caller's diff starts from the balance before buying the gas
+        let balance = self.state_db.get_balance(self.ctx.caller)?;
+        result
+            .state_diff
+            .record_balance(*self.ctx.caller, caller_balance, balance);
 
         let gas_limit = tx.gas().as_u64();
         let metadata = StackSubstateMetadata::new(gas_limit, config);
@@ -124,7 +177,7 @@ impl<'a, D: StateDB> Executor<'a, D> {
         let mut executor = StackExecutor::new_with_precompiles(mem_state, config, precompile_set);
 
         // check balance > gas_limit * gasPrice first
-        let (reason, data) = match tx.to() {
+        let transact = || match tx.to() {
             Some(to) => executor.transact_call(
                 self.ctx.caller.clone().into(),
                 to.into(),
@@ -141,6 +194,16 @@ impl<'a, D: StateDB> Executor<'a, D> {
                 access_list,
             ),
         };
+        let (reason, data) = match &self.tracer {
+            Some(tracer) => tracer.capture(transact),
+            None => transact(),
+        };
+
+        if let Some(err) = state.take_err() {
+            // the result is based on the default values, treat it as a fatal exit.
+            glog::error!("execute tx[{:?}] fail: {:?}", tx.hash().raw(), err);
+            return Err(ExecuteError::StateError(err));
+        }
 
         if matches!(reason, ExitReason::Fatal(ExitFatal::NotSupported)) {
             self.refund_gas()?;
@@ -168,6 +231,10 @@ impl<'a, D: StateDB> Executor<'a, D> {
         self.gas -= executor.used_gas();
 
         let (storages, logs) = executor.into_state().deconstruct();
+        result.storage_reads = state.take_reads();
+        if let Some(tracer) = &mut self.tracer {
+            tracer.capture_exit(self.state_db, &reason, &result.err)?;
+        }
 
         let mut log_index = 0;
         for log in logs {
@@ -197,6 +264,13 @@ impl<'a, D: StateDB> Executor<'a, D> {
                         reset_storage,
                     } => {
                         let address = address.into();
+                        self.record_account_diff(
+                            &mut result.state_diff,
+                            &address,
+                            basic.balance.into(),
+                            basic.nonce.as_u64(),
+                            code.as_ref(),
+                        )?;
                         if reset_storage {
                             self.state_db.suicide(&address).unwrap();
                         }
@@ -211,13 +285,27 @@ impl<'a, D: StateDB> Executor<'a, D> {
                             self.state_db.set_code(&address, code).unwrap();
                         }
                         for (index, value) in storage {
+                            // the slots cleared by reset_storage are read as zero here
+                            let index = index.into();
+                            let prev = self.state_db.get_state(&address, &index)?;
+                            result
+                                .state_diff
+                                .record_storage(address, index, prev, value.into());
                             self.state_db
-                                .set_state(&address, &index.into(), value.into())
+                                .set_state(&address, &index, value.into())
                                 .unwrap();
                         }
                     }
                     Apply::Delete { address } => {
-                        self.state_db.suicide(&address.into()).unwrap();
+                        let address = address.into();
+                        self.record_account_diff(
+                            &mut result.state_diff,
+                            &address,
+                            SU256::zero(),
+                            0,
+                            Some(&Vec::new()),
+                        )?;
+                        self.state_db.suicide(&address).unwrap();
                         // unreachable!("unsupport tx: {:?}", self.tx.hash());
                     }
                 }
@@ -235,6 +323,10 @@ impl<'a, D: StateDB> Executor<'a, D> {
                     } => {
                         let address = address.into();
                         if &address == self.ctx.caller {
+                            let prev = self.state_db.get_nonce(&address)?;
+                            result
+                                .state_diff
+                                .record_nonce(address, prev, basic.nonce.as_u64());
                             self.state_db
                                 .set_nonce(&address, basic.nonce.into())
                                 .unwrap();
@@ -273,11 +365,42 @@ impl<'a, D: StateDB> Executor<'a, D> {
         } else {
             &self.ctx.header.miner
         };
+        let miner_balance = self.state_db.get_balance(miner)?;
         self.state_db.add_balance(miner, &fee.clone().into())?;
         self.refund_gas()?;
+
+        // the gas payment is not part of the evm changes
+        let caller_balance_after = self.state_db.get_balance(self.ctx.caller)?;
+        result
+            .state_diff
+            .record_balance(*self.ctx.caller, caller_balance, caller_balance_after);
+        if !dry_run {
+            result
+                .state_diff
+                .record_balance(*miner, miner_balance, miner_balance + fee);
+        }
         Ok(result)
     }
 
+    fn record_account_diff(
+        &mut self,
+        diff: &mut StateDiff,
+        address: &SH160,
+        balance: SU256,
+        nonce: u64,
+        code: Option<&Vec<u8>>,
+    ) -> Result<(), ExecuteError> {
+        let prev_balance = self.state_db.get_balance(address)?;
+        diff.record_balance(*address, prev_balance, balance);
+        let prev_nonce = self.state_db.get_nonce(address)?;
+        diff.record_nonce(*address, prev_nonce, nonce);
+        if let Some(code) = code {
+            let prev_code = self.state_db.get_code(address)?;
+            diff.record_code(*address, prev_code.as_ref().clone(), code.clone().into());
+        }
+        Ok(())
+    }
+
     fn refund_gas(&mut self) -> Result<(), ExecuteError> {
         // Apply refund counter, capped to a refund quotient
         // REMINDER: already calculated in executor.gas_used();
@@ -335,6 +458,51 @@ impl<'a, D: StateDB> Executor<'a, D> {
         Ok(())
     }
 
+    fn validate(&self) -> Result<(), ExecuteError> {
+        let config = self.ctx.cfg;
+        let tx = &self.ctx.tx.tx;
+        let gas = tx.gas().as_u64();
+
+        let block_gas_limit = self.ctx.header.gas_limit.as_u64();
+        if gas > block_gas_limit {
+            return Err(ExecuteError::GasLimitExceeded {
+                gas,
+                block_gas_limit,
+            });
+        }
+
+        if tx.max_priority_fee_per_gas() > tx.max_fee_per_gas() {
+            return Err(ExecuteError::TipAboveFeeCap {
+                max_priority_fee_per_gas: tx.max_priority_fee_per_gas().clone(),
+                max_fee_per_gas: tx.max_fee_per_gas().clone(),
+            });
+        }
+
+        let input: &[u8] = tx.input();
+        let is_create = tx.to().is_none();
+        if is_create {
+            // EIP-3860
+            if let Some(limit) = config.max_initcode_size {
+                if input.len() > limit {
+                    return Err(ExecuteError::MaxInitCodeSizeExceeded {
+                        size: input.len(),
+                        limit,
+                    });
+                }
+            }
+        }
+
+        let access_list = tx.access_list().map(|n| &n[..]);
+        let intrinsic_gas = intrinsic_gas(config, is_create, input, access_list);
+        if gas < intrinsic_gas {
+            return Err(ExecuteError::IntrinsicGasTooLow {
+                have: gas,
+                want: intrinsic_gas,
+            });
+        }
+        Ok(())
+    }
+
     fn check_nonce(&mut self, try_get: bool, dry_run: bool) -> Result<(), ExecuteError> {
         let caller = self.ctx.caller;
         let tx_nonce = self.ctx.tx.tx.nonce();
@@ -363,6 +531,55 @@ impl<'a, D: StateDB> Executor<'a, D> {
     }
 }
 
+// the gas charged before the execution, see IntrinsicGas in geth
+pub fn intrinsic_gas(
+    config: &Config,
+    is_create: bool,
+    input: &[u8],
+    access_list: Option<&[TransactionAccessTuple]>,
+) -> u64 {
+    let mut gas = if is_create {
+        config.gas_transaction_create
+    } else {
+        config.gas_transaction_call
+    };
+    let zero_bytes = input.iter().filter(|n| **n == 0).count() as u64;
+    let non_zero_bytes = input.len() as u64 - zero_bytes;
+    gas += zero_bytes * config.gas_transaction_zero_data;
+    gas += non_zero_bytes * config.gas_transaction_non_zero_data;
+    if is_create && config.max_initcode_size.is_some() {
+        gas += (input.len() as u64 + 31) / 32 * INIT_CODE_WORD_GAS;
+    }
+    if let Some(al) = access_list {
+        gas += al.len() as u64 * config.gas_access_list_address;
+        let keys: usize = al.iter().map(|n| n.storage_keys.len()).sum();
+        gas += keys as u64 * config.gas_access_list_storage_key;
+    }
+    gas
+}
+
+// the address of the contract deployed by CREATE: keccak(rlp([caller, nonce]))[12..]
+pub fn create_address(caller: &SH160, nonce: u64) -> SH160 {
+    let nonce_bytes = nonce.to_be_bytes();
+    let nonce_bytes = &nonce_bytes[nonce.leading_zeros() as usize / 8..];
+
+    let mut stream = Vec::with_capacity(32);
+    stream.push(0); // the list header, the payload is always shorter than 56 bytes
+    stream.push(0x80 + 20);
+    stream.extend_from_slice(caller.as_bytes());
+    match nonce_bytes {
+        [n] if *n < 0x80 => stream.push(*n),
+        _ => {
+            stream.push(0x80 + nonce_bytes.len() as u8);
+            stream.extend_from_slice(nonce_bytes);
+        }
+    }
+    stream[0] = 0xc0 + (stream.len() - 1) as u8;
+
+    let hash = keccak_hash(&stream);
+    H160::from_slice(&hash[12..]).into()
+}
+
 #[derive(Debug)]
 pub enum ExecuteError {
     NotSupported,
@@ -380,6 +597,23 @@ pub enum ExecuteError {
         got: u64,
     },
     StateError(statedb::Error),
+    // the tx is invalid regardless of the state
+    IntrinsicGasTooLow {
+        have: u64,
+        want: u64,
+    },
+    MaxInitCodeSizeExceeded {
+        size: usize,
+        limit: usize,
+    },
+    TipAboveFeeCap {
+        max_priority_fee_per_gas: SU256,
+        max_fee_per_gas: SU256,
+    },
+    GasLimitExceeded {
+        gas: u64,
+        block_gas_limit: u64,
+    },
 }
 
 impl From<statedb::Error> for ExecuteError {
@@ -395,4 +629,42 @@ pub struct ExecuteResult {
     pub err: HexBytes, // Any error encountered during the execution(listed in core/vm/errors.go)
     pub return_data: Vec<u8>, // Returned data from evm(function result or data supplied with revert opcode)
     pub logs: Vec<Log>,
+    // the changes of the touched accounts, including the gas payment
+    pub state_diff: StateDiff,
+    // the storage slots read during the execution
+    pub storage_reads: BTreeSet<(SH160, SH256)>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_create_address() {
+        glog::init_test();
+        let addr = |hex: &str| -> SH160 {
+            let bytes = HexBytes::from_hex(hex.as_bytes()).unwrap();
+            H160::from_slice(&bytes).into()
+        };
+        let caller = addr("0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0");
+        let vectors = [
+            (0, "0xcd234a471b72ba2f1ccf0a70fcaba648a5eecd8d"),
+            (1, "0x343c43a37d37dff08ae8c4a11544c718abb4fcf8"),
+            (2, "0xf778b86fa74e846c4f0a1fbd1335fe81c00a0c91"),
+            (3, "0xfffd933a0bc612844eaf0c6fe3e5b8e9b6c1d19c"),
+            // the last single byte nonce and the multi-byte encodings
+            (0x7f, "0x06d9a77f5e4b311bae8d559db9cdb4df94104aa0"),
+            (0x80, "0x08e190dcb7b73f5fcdabb43e102215c83659a76d"),
+            (0x100, "0x3837c1ae70354f670550c746580199ac6a73cb0a"),
+            (u64::MAX, "0x9bc924993b60399df164c3763a964301d3db95ca"),
+        ];
+        for (nonce, expect) in vectors {
+            assert_eq!(
+                create_address(&caller, nonce),
+                addr(expect),
+                "nonce {}",
+                nonce
+            );
+        }
+    }
 }
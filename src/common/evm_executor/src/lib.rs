@@ -4,6 +4,10 @@
 #[macro_use]
 extern crate sgxlib as std;
 
+mod config;
+pub use config::*;
+mod chain_spec;
+pub use chain_spec::*;
 mod precompile;
 pub use precompile::*;
 mod state_proxy;
@@ -12,7 +16,21 @@ mod context;
 pub use context::*;
 mod executor;
 pub use executor::*;
+mod state_diff;
+pub use state_diff::*;
+mod system_contract;
+pub use system_contract::*;
+mod tracer;
+pub use tracer::*;
+mod proof;
+pub use proof::*;
+mod slot_predictor;
+pub use slot_predictor::*;
+mod state_backend;
+pub use state_backend::*;
 mod state_fetcher;
 pub use state_fetcher::*;
-
-pub use evm::Config;
\ No newline at end of file
+mod state_cache;
+pub use state_cache::*;
+mod state_coalescer;
+pub use state_coalescer::*;
\ No newline at end of file
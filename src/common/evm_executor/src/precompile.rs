@@ -5,12 +5,15 @@ use std::collections::BTreeMap;
 use crypto::{keccak_hash, secp256k1_ecdsa_recover, sha256_sum};
 use eth_types::{HexBytes, H160, U256};
 
+use bls12_381::{
+    multi_miller_loop, G1Affine, G1Projective, G2Affine, G2Prepared, G2Projective, Gt, Scalar,
+};
 use evm::{
     executor::stack::{
         IsPrecompileResult, PrecompileFailure, PrecompileHandle, PrecompileOutput,
         PrecompileSet as EvmPrecompileSet,
     },
-    ExitFatal, ExitSucceed,
+    ExitError, ExitFatal, ExitSucceed,
 };
 use num_bigint::BigUint;
 
@@ -24,10 +27,6 @@ pub struct PrecompileSet {
 impl PrecompileSet {
     pub fn berlin() -> Self {
         let mut def = Self::default();
-        for i in 1..=9 {
-            def.add(i, PrecompileUnimplemented { addr: i });
-        }
-
         def.add(1, PrecompileEcrecover {});
         def.add(2, PrecompileSha256Hash {});
         def.add(3, PrecompileRipemd160Hash {});
@@ -36,9 +35,24 @@ impl PrecompileSet {
         def.add(6, PrecompileAddIstanbul {});
         def.add(7, PrecompileMulIstanbul {});
         def.add(8, PrecompilePairIstanbul {});
-        // def.add(9, PrecompileBlake2F {});
         // 9: 0x6ad71132f7493ae1c13b4e2c2742ba9ad7432971815f48c188aa54bee9a7e9ce blake2F
+        def.add(9, PrecompileBlake2F {});
+
+        def
+    }
 
+    pub fn cancun() -> Self {
+        let mut def = Self::berlin();
+        def.add(10, PrecompilePointEvaluation {});
+        def
+    }
+
+    pub fn prague() -> Self {
+        let mut def = Self::cancun();
+        // EIP-2537: the BLS12-381 precompiles
+        for i in 11..=17 {
+            def.add(i, PrecompileUnimplemented { addr: i });
+        }
         def
     }
 
@@ -375,65 +389,238 @@ impl PrecompiledContract for PrecompileRipemd160Hash {
     }
 }
 
-// #[derive(Debug)]
-// pub struct PrecompileBlake2F {}
-
-// impl PrecompiledContract for PrecompileBlake2F {
-//     fn required_gas(&self, mut input: &[u8]) -> u64 {
-//         if input.len() != 213 {
-//             return 0;
-//         }
-//         let mut val = [0_u8; 4];
-//         val.copy_from_slice(&input[..4]);
-//         return u32::from_be_bytes(val) as u64;
-//     }
-
-//     fn run(&self, input: &[u8]) -> PrecompileResult {
-//         if input.len() != INPUT_LENGTH {
-//             return Err(PrecompileFailure::Revert {
-//                 exit_status: ExitRevert::Reverted,
-//                 output: "Invalid Length".into(),
-//             });
-//         }
-
-//         let f = match input[212] {
-//             1 => true,
-//             0 => false,
-//             _ => {
-//                 return Err(PrecompileFailure::Revert {
-//                     exit_status: ExitRevert::Reverted,
-//                     output: "Invalid Length".into(),
-//                 })
-//             }
-//         };
-
-//         // rounds 4 bytes
-//         let rounds = u32::from_be_bytes(input[..4].try_into().unwrap()) as usize;
-
-//         let mut h = [0u64; 8];
-//         let mut m = [0u64; 16];
-
-//         for (i, pos) in (4..68).step_by(8).enumerate() {
-//             h[i] = u64::from_le_bytes(input[pos..pos + 8].try_into().unwrap());
-//         }
-//         for (i, pos) in (68..196).step_by(8).enumerate() {
-//             m[i] = u64::from_le_bytes(input[pos..pos + 8].try_into().unwrap());
-//         }
-//         let t = [
-//             u64::from_le_bytes(input[196..196 + 8].try_into().unwrap()),
-//             u64::from_le_bytes(input[204..204 + 8].try_into().unwrap()),
-//         ];
-
-//         algo::compress(rounds, &mut h, m, t, f);
-
-//         let mut out = [0u8; 64];
-//         for (i, h) in (0..64).step_by(8).zip(h.iter()) {
-//             out[i..i + 8].copy_from_slice(&h.to_le_bytes());
-//         }
-
-//         Ok((gas_used, out.to_vec()))
-//     }
-// }
+/// Input length for the blake2 compression function.
+const BLAKE2F_INPUT_LEN: usize = 213;
+
+const BLAKE2B_IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+const BLAKE2B_SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+// EIP-152: https://eips.ethereum.org/EIPS/eip-152
+#[derive(Debug)]
+pub struct PrecompileBlake2F {}
+
+impl PrecompileBlake2F {
+    fn mix(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+        v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+        v[d] = (v[d] ^ v[a]).rotate_right(32);
+        v[c] = v[c].wrapping_add(v[d]);
+        v[b] = (v[b] ^ v[c]).rotate_right(24);
+        v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+        v[d] = (v[d] ^ v[a]).rotate_right(16);
+        v[c] = v[c].wrapping_add(v[d]);
+        v[b] = (v[b] ^ v[c]).rotate_right(63);
+    }
+
+    fn compress(rounds: usize, h: &mut [u64; 8], m: [u64; 16], t: [u64; 2], f: bool) {
+        let mut v = [0_u64; 16];
+        v[..8].copy_from_slice(h);
+        v[8..].copy_from_slice(&BLAKE2B_IV);
+        v[12] ^= t[0];
+        v[13] ^= t[1];
+        if f {
+            v[14] = !v[14];
+        }
+
+        for i in 0..rounds {
+            let s = &BLAKE2B_SIGMA[i % 10];
+            Self::mix(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+            Self::mix(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+            Self::mix(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+            Self::mix(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+            Self::mix(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+            Self::mix(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+            Self::mix(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+            Self::mix(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+        }
+
+        for i in 0..8 {
+            h[i] ^= v[i] ^ v[i + 8];
+        }
+    }
+}
+
+impl PrecompiledContract for PrecompileBlake2F {
+    fn required_gas(&self, input: &[u8]) -> u64 {
+        if input.len() != BLAKE2F_INPUT_LEN {
+            return 0;
+        }
+        let mut rounds = [0_u8; 4];
+        rounds.copy_from_slice(&input[..4]);
+        u32::from_be_bytes(rounds) as u64
+    }
+
+    fn run(&self, input: &[u8]) -> PrecompileResult {
+        if input.len() != BLAKE2F_INPUT_LEN {
+            return Err(precompile_error("invalid input length"));
+        }
+
+        let f = match input[212] {
+            1 => true,
+            0 => false,
+            _ => return Err(precompile_error("invalid final flag")),
+        };
+
+        let read_u64 = |pos: usize| {
+            let mut val = [0_u8; 8];
+            val.copy_from_slice(&input[pos..pos + 8]);
+            u64::from_le_bytes(val)
+        };
+
+        let rounds = self.required_gas(input) as usize;
+        let mut h = [0_u64; 8];
+        let mut m = [0_u64; 16];
+        for (i, pos) in (4..68).step_by(8).enumerate() {
+            h[i] = read_u64(pos);
+        }
+        for (i, pos) in (68..196).step_by(8).enumerate() {
+            m[i] = read_u64(pos);
+        }
+        let t = [read_u64(196), read_u64(204)];
+
+        Self::compress(rounds, &mut h, m, t, f);
+
+        let mut output = [0_u8; 64];
+        for (i, h) in h.iter().enumerate() {
+            output[i * 8..(i + 1) * 8].copy_from_slice(&h.to_le_bytes());
+        }
+        Ok(PrecompileOutput {
+            exit_status: ExitSucceed::Returned,
+            output: output.into(),
+        })
+    }
+}
+
+/// Input length for the point evaluation: versioned_hash, z, y, commitment, proof
+const POINT_EVALUATION_INPUT_LEN: usize = 192;
+
+const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+
+const FIELD_ELEMENTS_PER_BLOB: u64 = 4096;
+
+const BLS_MODULUS: [u8; 32] = [
+    0x73, 0xed, 0xa7, 0x53, 0x29, 0x9d, 0x7d, 0x48, 0x33, 0x39, 0xd8, 0x08, 0x09, 0xa1, 0xd8, 0x05,
+    0x53, 0xbd, 0xa4, 0x02, 0xff, 0xfe, 0x5b, 0xfe, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01,
+];
+
+/// [tau]G2 from the KZG ceremony, the only G2 point of the trusted setup the verification needs
+const KZG_SETUP_G2: [u8; 96] = [
+    0xb5, 0xbf, 0xd7, 0xdd, 0x8c, 0xde, 0xb1, 0x28, 0x84, 0x3b, 0xc2, 0x87, 0x23, 0x0a, 0xf3, 0x89,
+    0x26, 0x18, 0x70, 0x75, 0xcb, 0xfb, 0xef, 0xa8, 0x10, 0x09, 0xa2, 0xce, 0x61, 0x5a, 0xc5, 0x3d,
+    0x29, 0x14, 0xe5, 0x87, 0x0c, 0xb4, 0x52, 0xd2, 0xaf, 0xaa, 0xab, 0x24, 0xf3, 0x49, 0x9f, 0x72,
+    0x18, 0x5c, 0xbf, 0xee, 0x53, 0x49, 0x27, 0x14, 0x73, 0x44, 0x29, 0xb7, 0xb3, 0x86, 0x08, 0xe2,
+    0x39, 0x26, 0xc9, 0x11, 0xcc, 0xec, 0xea, 0xc9, 0xa3, 0x68, 0x51, 0x47, 0x7b, 0xa4, 0xc6, 0x0b,
+    0x08, 0x70, 0x41, 0xde, 0x62, 0x10, 0x00, 0xed, 0xc9, 0x8e, 0xda, 0xda, 0x20, 0xc1, 0xde, 0xf2,
+];
+
+// EIP-4844: https://eips.ethereum.org/EIPS/eip-4844#point-evaluation-precompile
+#[derive(Debug)]
+pub struct PrecompilePointEvaluation {}
+
+impl PrecompilePointEvaluation {
+    fn g1(buf: &[u8]) -> Option<G1Affine> {
+        let mut point = [0_u8; 48];
+        point.copy_from_slice(buf);
+        // the subgroup check is included
+        G1Affine::from_compressed(&point).into()
+    }
+
+    fn scalar(buf: &[u8]) -> Option<Scalar> {
+        // big endian in the input, must be less than the BLS_MODULUS
+        let mut scalar = [0_u8; 32];
+        scalar.copy_from_slice(buf);
+        scalar.reverse();
+        Scalar::from_bytes(&scalar).into()
+    }
+
+    // e(commitment - [y]G1, -G2) * e(proof, [tau]G2 - [z]G2) == 1
+    fn verify(&self, commitment: &[u8], z: &[u8], y: &[u8], proof: &[u8]) -> bool {
+        let (commitment, z, y, proof) = match (
+            Self::g1(commitment),
+            Self::scalar(z),
+            Self::scalar(y),
+            Self::g1(proof),
+        ) {
+            (Some(commitment), Some(z), Some(y), Some(proof)) => (commitment, z, y, proof),
+            _ => return false,
+        };
+        let setup: Option<G2Affine> = G2Affine::from_compressed(&KZG_SETUP_G2).into();
+        let setup = match setup {
+            Some(setup) => setup,
+            None => return false,
+        };
+
+        let p_minus_y = G1Affine::from(G1Projective::from(commitment) - G1Affine::generator() * y);
+        let x_minus_z = G2Affine::from(G2Projective::from(setup) - G2Affine::generator() * z);
+        let result = multi_miller_loop(&[
+            (&p_minus_y, &G2Prepared::from(-G2Affine::generator())),
+            (&proof, &G2Prepared::from(x_minus_z)),
+        ]);
+        result.final_exponentiation() == Gt::identity()
+    }
+}
+
+impl PrecompiledContract for PrecompilePointEvaluation {
+    fn required_gas(&self, _: &[u8]) -> u64 {
+        50000
+    }
+
+    fn run(&self, input: &[u8]) -> PrecompileResult {
+        if input.len() != POINT_EVALUATION_INPUT_LEN {
+            return Err(precompile_error("invalid input length"));
+        }
+
+        let versioned_hash = &input[..32];
+        let z = &input[32..64];
+        let y = &input[64..96];
+        let commitment = &input[96..144];
+        let proof = &input[144..192];
+
+        let mut hash = sha256_sum(commitment);
+        hash[0] = VERSIONED_HASH_VERSION_KZG;
+        if &hash[..] != versioned_hash {
+            return Err(precompile_error("mismatched versioned hash"));
+        }
+
+        if !self.verify(commitment, z, y, proof) {
+            return Err(precompile_error("verify proof failed"));
+        }
+
+        let mut output = [0_u8; 64];
+        U256::from(FIELD_ELEMENTS_PER_BLOB).to_big_endian(&mut output[..32]);
+        output[32..].copy_from_slice(&BLS_MODULUS);
+        Ok(PrecompileOutput {
+            exit_status: ExitSucceed::Returned,
+            output: output.into(),
+        })
+    }
+}
+
+fn precompile_error(msg: &'static str) -> PrecompileFailure {
+    PrecompileFailure::Error {
+        exit_status: ExitError::Other(msg.into()),
+    }
+}
 
 #[derive(Debug)]
 pub struct PrecompileBigModExp {
@@ -623,4 +810,66 @@ mod test {
         assert_eq!(expect, output);
         assert_eq!(contract.required_gas(&input), 200); // 16
     }
+
+    #[test]
+    fn test_blake2f() {
+        glog::init_test();
+        // test vectors from EIP-152
+        let cases = [
+            ("0x0000000048c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e1319cde05b61626300000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000300000000000000000000000000000001", "0x08c9bcf367e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d282e6ad7f520e511f6c3e2b8c68059b9442be0454267ce079217e1319cde05b"),
+            ("0x0000000c48c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e1319cde05b61626300000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000300000000000000000000000000000001", "0xba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"),
+            ("0x0000000c48c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e1319cde05b61626300000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000300000000000000000000000000000000", "0x75ab69d3190a562c51aef8d88f1c2775876944407270c42c9844252c26d2875298743e7f6d5ea2f2d3e8d226039cd31b4e426ac4f2d3d666a610c2116fde4735"),
+            ("0x0000000148c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e1319cde05b61626300000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000300000000000000000000000000000001", "0xb63a380cb2897d521994a85234ee2c181b5f844d2c624c002677e9703449d2fba551b3a8333bcdf5f2f7e08993d53923de3d64fcc68c034e717b9293fed7a421"),
+        ];
+        let contract = PrecompileBlake2F {};
+        for (input, expect) in cases {
+            let input = HexBytes::from_hex(input.as_bytes()).unwrap();
+            let expect = HexBytes::from_hex(expect.as_bytes()).unwrap();
+            let output: HexBytes = contract.run(&input).unwrap().output.into();
+            assert_eq!(expect, output);
+            assert_eq!(
+                contract.required_gas(&input),
+                u32::from_be_bytes([input[0], input[1], input[2], input[3]]) as u64
+            );
+        }
+
+        // invalid length
+        let input = HexBytes::from_hex(b"0x00000c").unwrap();
+        assert!(contract.run(&input).is_err());
+        // invalid final flag
+        let input = HexBytes::from_hex(b"0x0000000c48c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e1319cde05b61626300000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000300000000000000000000000000000002").unwrap();
+        assert!(contract.run(&input).is_err());
+    }
+
+    #[test]
+    fn test_point_evaluation() {
+        glog::init_test();
+        // test vector from go-ethereum core/vm/testdata/precompiles/pointEvaluation.json
+        let mut input = HexBytes::from_hex(b"0x01e798154708fe7789429634053cbf9f99b619f9f084048927333fce637f549b564c0a11a0f704f4fc3e8acfe0f8245f0ad1347b378fbf96e206da11a5d3630624d25032e67a7e6a4910df5834b8fe70e6bcfeeac0352434196bdf4b2485d5a18f59a8d2a1a625a17f3fea0fe5eb8c896db3764f3185481bc22f91b4aaffcca25f26936857bc3a7c2539ea8ec3a952b7873033e038326e87ed3e1276fd140253fa08e9fc25fb2d9a98527fc22a2c9612fbeafdad446cbc7bcdbdcd780af2c16a").unwrap().to_vec();
+        let expect = HexBytes::from_hex(
+            b"0x000000000000000000000000000000000000000000000000000000000000100073eda753299d7d483339d80809a1d80553bda402fffe5bfeffffffff00000001",
+        )
+        .unwrap();
+        let contract = PrecompilePointEvaluation {};
+        let output: HexBytes = contract.run(&input).unwrap().output.into();
+        assert_eq!(expect, output);
+        assert_eq!(contract.required_gas(&input), 50000);
+
+        // the versioned hash doesn't match the commitment
+        input[0] = 0x02;
+        assert!(contract.run(&input).is_err());
+        input[0] = 0x01;
+
+        // a wrong y
+        input[95] ^= 0x01;
+        assert!(contract.run(&input).is_err());
+        input[95] ^= 0x01;
+
+        // z is not less than the BLS_MODULUS
+        input[32..64].copy_from_slice(&BLS_MODULUS);
+        assert!(contract.run(&input).is_err());
+
+        // invalid length
+        assert!(contract.run(&input[..191]).is_err());
+    }
 }
@@ -0,0 +1,327 @@
+use std::prelude::v1::*;
+
+use crypto::keccak_hash;
+use eth_types::{HexBytes, H256, SH256, U256};
+use rlp::{DecoderError, Rlp};
+
+// keccak(rlp(""))
+pub const EMPTY_ROOT: [u8; 32] = [
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+];
+
+// keccak("")
+pub const EMPTY_CODE_HASH: [u8; 32] = [
+    0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7, 0x03, 0xc0,
+    0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+];
+
+// the state served by the remote doesn't match the proof. statedb::Error can't be extended, so
+// it's carried by CallRemoteFail with a prefix, use `from_state_error` to tell it apart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProofError(pub String);
+
+impl ProofError {
+    const PREFIX: &'static str = "[verify] ";
+
+    pub fn from_state_error(err: &statedb::Error) -> Option<Self> {
+        match err {
+            statedb::Error::CallRemoteFail(msg) => {
+                msg.strip_prefix(Self::PREFIX).map(|msg| Self(msg.into()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ProofError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", Self::PREFIX, self.0)
+    }
+}
+
+impl From<ProofError> for statedb::Error {
+    fn from(err: ProofError) -> Self {
+        statedb::Error::CallRemoteFail(err.to_string())
+    }
+}
+
+impl From<&str> for ProofError {
+    fn from(msg: &str) -> Self {
+        Self(msg.into())
+    }
+}
+
+impl From<String> for ProofError {
+    fn from(msg: String) -> Self {
+        Self(msg)
+    }
+}
+
+impl From<DecoderError> for ProofError {
+    fn from(err: DecoderError) -> Self {
+        Self(format!("invalid rlp: {:?}", err))
+    }
+}
+
+// the account decoded from a verified account proof
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProvedAccount {
+    pub nonce: u64,
+    pub balance: U256,
+    pub storage_root: SH256,
+    pub code_hash: SH256,
+}
+
+impl ProvedAccount {
+    pub fn empty() -> Self {
+        Self {
+            nonce: 0,
+            balance: U256::zero(),
+            storage_root: H256::from(EMPTY_ROOT).into(),
+            code_hash: H256::from(EMPTY_CODE_HASH).into(),
+        }
+    }
+}
+
+// checks the account proof against the state root, returns the empty account if the proof
+// shows the account doesn't exist.
+pub fn verify_account_proof(
+    state_root: &SH256,
+    address: &[u8],
+    proof: &[HexBytes],
+) -> Result<ProvedAccount, ProofError> {
+    let value = match verify_proof(state_root, &keccak_hash(address), proof)? {
+        Some(value) => value,
+        None => return Ok(ProvedAccount::empty()),
+    };
+    decode_account(&value)
+}
+
+// checks the storage proof against the storage root of the account, returns the value of the slot
+pub fn verify_storage_proof(
+    storage_root: &SH256,
+    key: &SH256,
+    proof: &[HexBytes],
+) -> Result<SH256, ProofError> {
+    match verify_proof(storage_root, &keccak_hash(key.as_bytes()), proof)? {
+        Some(value) => decode_storage(&value),
+        None => Ok(SH256::default()),
+    }
+}
+
+// the account stored in the state trie: rlp([nonce, balance, storage_root, code_hash])
+pub fn decode_account(value: &[u8]) -> Result<ProvedAccount, ProofError> {
+    let account = decode(value)?;
+    if !account.is_list() || account.item_count()? != 4 {
+        return Err("invalid account".into());
+    }
+    let balance = account.at(1)?.data()?;
+    if balance.len() > 32 {
+        return Err("invalid account balance".into());
+    }
+    Ok(ProvedAccount {
+        nonce: account.val_at(0)?,
+        balance: U256::from_big_endian(balance),
+        storage_root: as_hash(&account.at(2)?)?,
+        code_hash: as_hash(&account.at(3)?)?,
+    })
+}
+
+// the slot value stored in the storage trie, rlp of the value without the leading zeros
+pub fn decode_storage(value: &[u8]) -> Result<SH256, ProofError> {
+    let value = decode(value)?.data()?;
+    if value.len() > 32 {
+        return Err("invalid storage value".into());
+    }
+    let mut out = [0_u8; 32];
+    out[32 - value.len()..].copy_from_slice(value);
+    Ok(H256::from(out).into())
+}
+
+// walks the merkle patricia trie proof from the root, returns the value of the path or None if
+// the proof shows it doesn't exist.
+pub fn verify_proof(
+    root: &SH256,
+    path: &[u8; 32],
+    proof: &[HexBytes],
+) -> Result<Option<Vec<u8>>, ProofError> {
+    let mut proof = proof.iter();
+    walk_trie(root, path, |_| {
+        proof
+            .next()
+            .cloned()
+            .ok_or_else(|| "the proof is incomplete".into())
+    })
+}
+
+// walks the trie from the root along the path, the nodes referenced by hash are loaded with
+// `load` and checked against the hash, so the nodes on the path form the proof of the value.
+pub fn walk_trie<F, E>(root: &SH256, path: &[u8; 32], mut load: F) -> Result<Option<Vec<u8>>, E>
+where
+    F: FnMut(&SH256) -> Result<HexBytes, E>,
+    E: From<ProofError>,
+{
+    if root.as_bytes() == EMPTY_ROOT {
+        return Ok(None);
+    }
+    let nibbles: Vec<u8> = path.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect();
+    let mut pos = 0;
+    let mut node = load_node(&mut load, root)?;
+    loop {
+        node = match walk_node(&node, &nibbles, &mut pos)? {
+            TrieStep::Value(value) => return Ok(value),
+            TrieStep::Embedded(node) => node,
+            TrieStep::Hash(hash) => load_node(&mut load, &hash)?,
+        };
+    }
+}
+
+enum TrieStep {
+    Value(Option<Vec<u8>>),
+    // the nodes shorter than 32 bytes are embedded in the parent
+    Embedded(Vec<u8>),
+    Hash(SH256),
+}
+
+fn load_node<F, E>(load: &mut F, hash: &SH256) -> Result<Vec<u8>, E>
+where
+    F: FnMut(&SH256) -> Result<HexBytes, E>,
+    E: From<ProofError>,
+{
+    let node = load(hash)?;
+    if keccak_hash(&node).as_slice() != hash.as_bytes() {
+        return Err(ProofError(format!("node hash mismatch: {:?}", hash)).into());
+    }
+    Ok(node.to_vec())
+}
+
+fn walk_node(node: &[u8], nibbles: &[u8], pos: &mut usize) -> Result<TrieStep, ProofError> {
+    let node = decode(node)?;
+    if !node.is_list() {
+        return Err("invalid trie node".into());
+    }
+    let child = match node.item_count()? {
+        17 => {
+            // the key is fixed-length, so the value is never stored in a branch
+            let nibble = *nibbles
+                .get(*pos)
+                .ok_or("the proof is longer than the path")?;
+            *pos += 1;
+            node.at(nibble as usize)?
+        }
+        2 => {
+            let (is_leaf, key) = decode_hex_prefix(node.at(0)?.data()?)?;
+            let rest = &nibbles[*pos..];
+            if is_leaf {
+                return match rest == key.as_slice() {
+                    true => Ok(TrieStep::Value(Some(node.at(1)?.data()?.to_vec()))),
+                    false => Ok(TrieStep::Value(None)),
+                };
+            }
+            if !rest.starts_with(&key) {
+                return Ok(TrieStep::Value(None));
+            }
+            *pos += key.len();
+            node.at(1)?
+        }
+        _ => return Err("invalid trie node".into()),
+    };
+    if child.is_list() {
+        return Ok(TrieStep::Embedded(child.as_raw().to_vec()));
+    }
+    match child.data()? {
+        hash if hash.is_empty() => Ok(TrieStep::Value(None)),
+        hash if hash.len() == 32 => Ok(TrieStep::Hash(H256::from_slice(hash).into())),
+        _ => Err("invalid child reference".into()),
+    }
+}
+
+// the rlp of a whole item, without the trailing bytes
+fn decode(data: &[u8]) -> Result<Rlp, ProofError> {
+    let rlp = Rlp::new(data);
+    if rlp.payload_info()?.total() != data.len() {
+        return Err("trailing bytes in rlp".into());
+    }
+    Ok(rlp)
+}
+
+fn as_hash(rlp: &Rlp) -> Result<SH256, ProofError> {
+    let data = rlp.data()?;
+    if data.len() != 32 {
+        return Err("invalid hash".into());
+    }
+    Ok(H256::from_slice(data).into())
+}
+
+// returns whether it's a leaf and the nibbles of the key
+fn decode_hex_prefix(data: &[u8]) -> Result<(bool, Vec<u8>), ProofError> {
+    let first = *data.first().ok_or("empty node key")?;
+    let flag = first >> 4;
+    if flag > 3 {
+        return Err("invalid node key".into());
+    }
+    let mut nibbles = Vec::with_capacity(data.len() * 2);
+    if flag & 1 == 1 {
+        nibbles.push(first & 0x0f);
+    }
+    for b in &data[1..] {
+        nibbles.push(b >> 4);
+        nibbles.push(b & 0x0f);
+    }
+    Ok((flag >= 2, nibbles))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rlp::RlpStream;
+
+    fn hash_of(node: &[u8]) -> SH256 {
+        H256::from(keccak_hash(node)).into()
+    }
+
+    // rlp([hex_prefix(path), value])
+    fn short_node(key: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&key.to_vec()).append(&value.to_vec());
+        stream.out().to_vec()
+    }
+
+    #[test]
+    fn test_verify_single_leaf() {
+        glog::init_test();
+        let key = SH256::from(H256::from([0x11; 32]));
+        let path = keccak_hash(key.as_bytes());
+        // the leaf keeps the whole path: 0x20 ++ path
+        let mut leaf_key = vec![0x20];
+        leaf_key.extend_from_slice(&path);
+        let leaf = short_node(&leaf_key, &[0x82, 0x12, 0x34]);
+        let root = hash_of(&leaf);
+
+        let proof = vec![HexBytes::from(leaf.clone())];
+        let value = verify_storage_proof(&root, &key, &proof).unwrap();
+        let mut expect = [0_u8; 32];
+        expect[30..].copy_from_slice(&[0x12, 0x34]);
+        assert_eq!(value, H256::from(expect).into());
+
+        // other keys are proved to be absent
+        let other = SH256::from(H256::from([0x22; 32]));
+        assert_eq!(
+            verify_storage_proof(&root, &other, &proof).unwrap(),
+            SH256::default()
+        );
+
+        // the proof doesn't match the root
+        let fake = vec![HexBytes::from(short_node(&leaf_key, &[0x82, 0x56, 0x78]))];
+        assert!(verify_storage_proof(&root, &key, &fake).is_err());
+    }
+
+    #[test]
+    fn test_verify_empty_trie() {
+        glog::init_test();
+        let root = H256::from(EMPTY_ROOT).into();
+        let account = verify_account_proof(&root, &[0x33; 20], &[]).unwrap();
+        assert_eq!(account, ProvedAccount::empty());
+    }
+}
@@ -0,0 +1,257 @@
+use std::prelude::v1::*;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+use eth_types::{TransactionAccessTuple, TransactionInner, SH160, SH256};
+use serde::Serialize;
+
+// the slots kept for each (contract, selector), the ones seen the least are dropped first
+const MAX_SLOTS_PER_CALL: usize = 128;
+
+// learns the storage slots each (contract, selector) read in the past executions, so the txs
+// without an access list can be prefetched before they are executed.
+#[derive(Clone, Debug, Default)]
+pub struct SlotPredictor(Arc<Mutex<SlotPredictorInner>>);
+
+#[derive(Debug, Default)]
+struct SlotPredictorInner {
+    // the predictor is cleared when it learned more calls than this, 0 means unlimited
+    limit: usize,
+    calls: BTreeMap<(SH160, [u8; 4]), CallSlots>,
+    stat: PredictStat,
+}
+
+#[derive(Debug, Default)]
+struct CallSlots {
+    execs: u64,
+    // how many executions read the slot
+    slots: BTreeMap<(SH160, SH256), u64>,
+}
+
+impl CallSlots {
+    // the slots read by at least half of the executions, the others are likely depending on the
+    // caller or the arguments.
+    fn predict(&self) -> impl Iterator<Item = &(SH160, SH256)> {
+        self.slots
+            .iter()
+            .filter(move |(_, seen)| **seen * 2 >= self.execs)
+            .map(|(slot, _)| slot)
+    }
+
+    fn learn(&mut self, reads: &BTreeSet<(SH160, SH256)>) {
+        self.execs += 1;
+        for slot in reads {
+            *self.slots.entry(*slot).or_default() += 1;
+        }
+        // only the excess is dropped, the ones seen equally are all kept otherwise
+        if self.slots.len() > MAX_SLOTS_PER_CALL {
+            let excess = self.slots.len() - MAX_SLOTS_PER_CALL;
+            let mut slots = self
+                .slots
+                .iter()
+                .map(|(slot, seen)| (*seen, *slot))
+                .collect::<Vec<_>>();
+            slots.sort();
+            for (_, slot) in slots.into_iter().take(excess) {
+                self.slots.remove(&slot);
+            }
+        }
+    }
+}
+
+// the hits and misses of the predicted slots, compared with the slots actually read. only the
+// txs prefetched with the predicted slots are counted.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PredictStat {
+    pub calls: usize,
+    pub predicted: u64,
+    // the predicted slots which are read
+    pub hits: u64,
+    // the slots read but not predicted
+    pub misses: u64,
+}
+
+impl SlotPredictor {
+    pub fn new(limit: usize) -> Self {
+        let predictor = Self::default();
+        predictor.0.lock().unwrap().limit = limit;
+        predictor
+    }
+
+    fn key(tx: &TransactionInner) -> Option<(SH160, [u8; 4])> {
+        let to = tx.to()?;
+        let input: &[u8] = tx.input();
+        if input.len() < 4 {
+            return None;
+        }
+        let mut selector = [0_u8; 4];
+        selector.copy_from_slice(&input[..4]);
+        Some((to, selector))
+    }
+
+    // returns the slots the tx is likely to read, grouped by the contracts
+    pub fn predict(&self, tx: &TransactionInner) -> Vec<TransactionAccessTuple> {
+        let key = match Self::key(tx) {
+            Some(key) => key,
+            None => return Vec::new(),
+        };
+        let inner = self.0.lock().unwrap();
+        let call = match inner.calls.get(&key) {
+            Some(call) => call,
+            None => return Vec::new(),
+        };
+        let mut out: Vec<TransactionAccessTuple> = Vec::new();
+        for (address, key) in call.predict() {
+            match out.last_mut() {
+                Some(item) if &item.address == address => item.storage_keys.push(*key),
+                _ => out.push(TransactionAccessTuple {
+                    address: *address,
+                    storage_keys: vec![*key],
+                }),
+            }
+        }
+        out
+    }
+
+    // records the slots read by an execution of the tx, `prefetched` tells whether the slots
+    // predicted for the tx were prefetched.
+    pub fn learn(&self, tx: &TransactionInner, reads: &BTreeSet<(SH160, SH256)>, prefetched: bool) {
+        let key = match Self::key(tx) {
+            Some(key) => key,
+            None => return,
+        };
+        let mut inner = self.0.lock().unwrap();
+        if inner.limit > 0 && !inner.calls.contains_key(&key) && inner.calls.len() >= inner.limit {
+            glog::info!("slot predictor is full({}), clear it", inner.limit);
+            inner.calls.clear();
+        }
+
+        let call = inner.calls.entry(key).or_default();
+        let predicted = call.predict().cloned().collect::<BTreeSet<_>>();
+        call.learn(reads);
+        if !prefetched {
+            return;
+        }
+        let hits = predicted.intersection(reads).count() as u64;
+        inner.stat.predicted += predicted.len() as u64;
+        inner.stat.hits += hits;
+        inner.stat.misses += reads.len() as u64 - hits;
+    }
+
+    pub fn stat(&self) -> PredictStat {
+        let inner = self.0.lock().unwrap();
+        PredictStat {
+            calls: inner.calls.len(),
+            ..inner.stat.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use eth_types::{DynamicFeeTx, HexBytes, H160, H256};
+
+    fn call(to: u8, selector: u8) -> TransactionInner {
+        TransactionInner::DynamicFee(DynamicFeeTx {
+            to: Some(H160::from([to; 20]).into()).into(),
+            data: HexBytes::from(vec![selector, 0, 0, 0, 1]),
+            ..Default::default()
+        })
+    }
+
+    fn slot(n: u64) -> (SH160, SH256) {
+        (H160::from([1; 20]).into(), H256::from_low_u64_be(n).into())
+    }
+
+    fn reads(slots: &[u64]) -> BTreeSet<(SH160, SH256)> {
+        slots.iter().map(|n| slot(*n)).collect()
+    }
+
+    fn predicted(predictor: &SlotPredictor, tx: &TransactionInner) -> BTreeSet<(SH160, SH256)> {
+        let mut out = BTreeSet::new();
+        for item in predictor.predict(tx) {
+            for key in item.storage_keys {
+                out.insert((item.address, key));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_predict() {
+        glog::init_test();
+        let predictor = SlotPredictor::new(0);
+        let tx = call(1, 1);
+        assert!(predictor.predict(&tx).is_empty());
+
+        predictor.learn(&tx, &reads(&[1, 2]), true);
+        predictor.learn(&tx, &reads(&[1, 3]), true);
+        predictor.learn(&tx, &reads(&[1, 4]), true);
+        // the slots read by less than half of the executions are not predicted
+        assert_eq!(predicted(&predictor, &tx), reads(&[1]));
+        // the selector is part of the key
+        assert!(predictor.predict(&call(1, 2)).is_empty());
+        assert!(predictor.predict(&call(2, 1)).is_empty());
+
+        let stat = predictor.stat();
+        assert_eq!(stat.calls, 1);
+        // nothing predicted for the first one, [1, 2] for the second and [1, 2, 3] for the third
+        assert_eq!(stat.predicted, 5);
+        assert_eq!(stat.hits, 2);
+        assert_eq!(stat.misses, 4);
+    }
+
+    #[test]
+    fn test_stat_prefetched() {
+        glog::init_test();
+        let predictor = SlotPredictor::new(0);
+        let tx = call(1, 1);
+        predictor.learn(&tx, &reads(&[1]), false);
+        predictor.learn(&tx, &reads(&[1, 2]), false);
+        let stat = predictor.stat();
+        assert_eq!((stat.predicted, stat.hits, stat.misses), (0, 0, 0));
+        // still learned from the txs not prefetched
+        assert_eq!(predicted(&predictor, &tx), reads(&[1, 2]));
+
+        predictor.learn(&tx, &reads(&[1, 3]), true);
+        let stat = predictor.stat();
+        assert_eq!((stat.predicted, stat.hits, stat.misses), (2, 1, 1));
+    }
+
+    #[test]
+    fn test_evict() {
+        glog::init_test();
+        let predictor = SlotPredictor::new(0);
+        let tx = call(1, 1);
+        let all = (0..MAX_SLOTS_PER_CALL as u64 + 10).collect::<Vec<_>>();
+        // every slot is seen once, only the excess is dropped
+        predictor.learn(&tx, &reads(&all), true);
+        assert_eq!(predicted(&predictor, &tx).len(), MAX_SLOTS_PER_CALL);
+
+        // the ones seen the least are dropped first
+        let predictor = SlotPredictor::new(0);
+        let hot = (0..MAX_SLOTS_PER_CALL as u64).collect::<Vec<_>>();
+        predictor.learn(&tx, &reads(&hot), true);
+        predictor.learn(&tx, &reads(&hot), true);
+        predictor.learn(&tx, &reads(&[1000, 1001]), true);
+        assert_eq!(predicted(&predictor, &tx), reads(&hot));
+    }
+
+    #[test]
+    fn test_limit() {
+        glog::init_test();
+        let predictor = SlotPredictor::new(2);
+        predictor.learn(&call(1, 1), &reads(&[1]), true);
+        predictor.learn(&call(2, 1), &reads(&[1]), true);
+        assert_eq!(predictor.stat().calls, 2);
+        // the learned calls are not counted again
+        predictor.learn(&call(2, 1), &reads(&[1]), true);
+        assert_eq!(predictor.stat().calls, 2);
+        // cleared when a new call is learned
+        predictor.learn(&call(3, 1), &reads(&[1]), true);
+        assert_eq!(predictor.stat().calls, 1);
+        assert!(predictor.predict(&call(1, 1)).is_empty());
+    }
+}
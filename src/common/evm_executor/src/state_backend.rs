@@ -0,0 +1,175 @@
+use std::prelude::v1::*;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use base::format::debug;
+use eth_client::ExecutionClient;
+use eth_types::{BlockSelector, SH160, SH256};
+use serde::{Deserialize, Serialize};
+
+// the rpc methods the states can be read with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateBackend {
+    // debug_dbGet, reads the trie nodes by hash. only the nodes with the hash-based scheme expose
+    // it, hosted providers usually don't.
+    DbGet,
+    // eth_getProof
+    Proof,
+    // eth_getBalance, eth_getTransactionCount, eth_getCode and eth_getStorageAt
+    Plain,
+}
+
+impl StateBackend {
+    // the default order of preference
+    pub fn all() -> Vec<Self> {
+        vec![Self::DbGet, Self::Proof, Self::Plain]
+    }
+
+    fn probe(
+        &self,
+        client: &ExecutionClient,
+        blk: BlockSelector,
+        state_root: &SH256,
+    ) -> Result<(), String> {
+        match self {
+            Self::DbGet => {
+                let nodes = client.get_dbnodes(&[*state_root]).map_err(debug)?;
+                match nodes.first() {
+                    Some(node) if !node.is_empty() => Ok(()),
+                    _ => Err("the state root is not found".into()),
+                }
+            }
+            Self::Proof => {
+                client
+                    .get_proof(&SH160::default(), &[], blk)
+                    .map_err(debug)?;
+                Ok(())
+            }
+            Self::Plain => {
+                client
+                    .get_storage(&SH160::default(), &SH256::default(), blk)
+                    .map_err(debug)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+// the backends supported by an endpoint in the configured order of preference, and the number of
+// the misses served by each of them. the clones share the counters.
+#[derive(Clone, Debug)]
+pub struct StateBackends {
+    chain: Vec<StateBackend>,
+    served: Arc<Mutex<BTreeMap<StateBackend, u64>>>,
+}
+
+impl Default for StateBackends {
+    fn default() -> Self {
+        Self::new(StateBackend::all())
+    }
+}
+
+impl StateBackends {
+    pub fn new(backends: Vec<StateBackend>) -> Self {
+        let mut chain = Vec::with_capacity(backends.len());
+        for backend in backends {
+            if !chain.contains(&backend) {
+                chain.push(backend);
+            }
+        }
+        Self {
+            chain,
+            served: Default::default(),
+        }
+    }
+
+    // keeps the wanted backends which the endpoint supports. the endpoint is assumed to support
+    // all of them if the head can't be fetched, so it still works as before.
+    pub fn probe(client: &ExecutionClient, wanted: &[StateBackend]) -> Self {
+        let head = match client.get_block_header(BlockSelector::Latest) {
+            Ok(head) => head,
+            Err(err) => {
+                glog::error!("probe state backends fail: {:?}", err);
+                return Self::new(wanted.to_vec());
+            }
+        };
+        let mut chain = Vec::with_capacity(wanted.len());
+        for backend in wanted {
+            match backend.probe(client, head.number.into(), &head.state_root) {
+                Ok(()) => chain.push(*backend),
+                Err(err) => glog::warn!("state backend {:?} is not supported: {}", backend, err),
+            }
+        }
+        let backends = Self::new(chain);
+        glog::info!("state backends: {:?}", backends.chain);
+        backends
+    }
+
+    // the same backends with their own counters
+    pub fn fork(&self) -> Self {
+        Self::new(self.chain.clone())
+    }
+
+    pub fn supports(&self, backend: StateBackend) -> bool {
+        self.chain.contains(&backend)
+    }
+
+    pub fn chain(&self) -> &[StateBackend] {
+        &self.chain
+    }
+
+    // the supported backends of the candidates, in the configured order
+    pub fn select(&self, candidates: &[StateBackend]) -> Result<Vec<StateBackend>, String> {
+        let selected: Vec<_> = self
+            .chain
+            .iter()
+            .filter(|n| candidates.contains(n))
+            .cloned()
+            .collect();
+        if selected.is_empty() {
+            return Err(format!(
+                "[backend] none of {:?} is supported, available: {:?}",
+                candidates, self.chain
+            ));
+        }
+        Ok(selected)
+    }
+
+    pub fn served(&self, backend: StateBackend) {
+        *self.served.lock().unwrap().entry(backend).or_default() += 1;
+    }
+
+    // returns and resets the number of the misses served by each backend
+    pub fn take_usage(&self) -> BTreeMap<StateBackend, u64> {
+        std::mem::take(&mut *self.served.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        glog::init_test();
+        use StateBackend::*;
+        // the configured order is kept and the duplicates are dropped
+        let backends = StateBackends::new(vec![Plain, Proof, Plain]);
+        assert_eq!(backends.chain(), &[Plain, Proof]);
+
+        assert_eq!(
+            backends.select(&[DbGet, Proof, Plain]).unwrap(),
+            vec![Plain, Proof]
+        );
+        assert_eq!(backends.select(&[Proof, DbGet]).unwrap(), vec![Proof]);
+        assert!(backends.select(&[DbGet]).is_err());
+
+        let backends = StateBackends::new(StateBackend::all());
+        assert_eq!(
+            backends.select(&[Plain, DbGet]).unwrap(),
+            vec![DbGet, Plain]
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use std::prelude::v1::*;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use super::{AccountRecord, BEACON_ROOTS_ADDRESS, HISTORY_STORAGE_ADDRESS};
+use eth_client::{ExecutionClient, TxStateDiff};
+use eth_types::{BlockHeader, HexBytes, Withdrawal, H160, SH160, SH256};
+use serde::Deserialize;
+
+// keeps the states fetched from the remote across blocks. when moving to the next block, only
+// the entries changed by it are dropped, so the hot accounts don't need to be fetched again.
+// the entries are keyed by the block hash, the blocks of a reorg never share the entries.
+#[derive(Clone, Debug, Default)]
+pub struct StateCache(Arc<Mutex<StateCacheInner>>);
+
+#[derive(Debug, Default)]
+struct StateCacheInner {
+    // the block the entries belong to
+    number: u64,
+    hash: SH256,
+    // the block whose state diff is being fetched
+    advancing: Option<SH256>,
+    // the cache is cleared when it has more entries than this, 0 means unlimited
+    limit: usize,
+    size: usize,
+    accounts: BTreeMap<SH160, AccountRecord>,
+    storages: BTreeMap<SH160, BTreeMap<SH256, SH256>>,
+    codes: BTreeMap<SH160, HexBytes>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockWithdrawals {
+    #[serde(default)]
+    withdrawals: Option<Vec<Withdrawal>>,
+}
+
+impl StateCacheInner {
+    fn reset(&mut self, header: &BlockHeader) {
+        self.number = header.number.as_u64();
+        self.hash = header.hash();
+        self.advancing = None;
+        self.size = 0;
+        self.accounts.clear();
+        self.storages.clear();
+        self.codes.clear();
+    }
+
+    fn grow(&mut self) {
+        self.size += 1;
+        if self.limit > 0 && self.size > self.limit {
+            glog::info!("state cache is full({}), clear it", self.limit);
+            self.size = 0;
+            self.accounts.clear();
+            self.storages.clear();
+            self.codes.clear();
+        }
+    }
+
+    fn invalidate(&mut self, diffs: &[TxStateDiff], withdrawals: &[Withdrawal]) {
+        for diff in diffs {
+            let result = &diff.result;
+            for (address, acc) in result.pre.iter().chain(result.post.iter()) {
+                self.accounts.remove(address);
+                self.codes.remove(address);
+                if !result.post.contains_key(address) {
+                    // the account is deleted
+                    self.storages.remove(address);
+                    continue;
+                }
+                if let Some(storages) = self.storages.get_mut(address) {
+                    for key in acc.storage.keys() {
+                        storages.remove(key);
+                    }
+                }
+            }
+        }
+        for withdrawal in withdrawals {
+            self.accounts.remove(&withdrawal.address);
+        }
+        // the system calls don't show up in the tx traces
+        for address in [BEACON_ROOTS_ADDRESS, HISTORY_STORAGE_ADDRESS] {
+            let address = H160::from(address).into();
+            self.accounts.remove(&address);
+            self.storages.remove(&address);
+        }
+    }
+}
+
+impl StateCache {
+    pub fn new(limit: usize) -> Self {
+        let cache = Self::default();
+        cache.0.lock().unwrap().limit = limit;
+        cache
+    }
+
+    // moves the cache to the given block. the state diff of the block is fetched in the
+    // background, the reads of the block miss the cache until it's done. the cache is cleared
+    // if the block is not the child of the current one.
+    pub fn advance(&self, client: &Arc<ExecutionClient>, header: &BlockHeader) {
+        let hash = header.hash();
+        {
+            let mut inner = self.0.lock().unwrap();
+            if inner.hash == hash || inner.advancing == Some(hash) {
+                return;
+            }
+            if inner.hash != header.parent_hash {
+                glog::info!(
+                    "state cache reset: #{} -> #{}",
+                    inner.number,
+                    header.number.as_u64()
+                );
+                inner.reset(header);
+                return;
+            }
+            inner.advancing = Some(hash);
+        }
+
+        let number = header.number.as_u64();
+        base::thread::spawn(format!("state-cache-{}", number), {
+            let cache = self.clone();
+            let client = client.clone();
+            let header = header.clone();
+            move || {
+                let selector = header.number.into();
+                let diffs = client.trace_block_state_diff(selector);
+                let withdrawals = client.get_block_generic::<BlockWithdrawals>(selector, false);
+
+                let mut inner = cache.0.lock().unwrap();
+                if inner.advancing == Some(hash) {
+                    inner.advancing = None;
+                }
+                if inner.hash != header.parent_hash {
+                    // moved to another block in the meantime
+                    return;
+                }
+                match (diffs, withdrawals) {
+                    (Ok(diffs), Ok(blk)) => {
+                        inner.invalidate(&diffs, &blk.withdrawals.unwrap_or_default());
+                        inner.number = number;
+                        inner.hash = hash;
+                    }
+                    (Err(err), _) | (_, Err(err)) => {
+                        glog::error!("fetch the state diff of #{} fail: {:?}", number, err);
+                        inner.reset(&header);
+                    }
+                }
+            }
+        });
+    }
+
+    pub fn get_account(&self, hash: &SH256, address: &SH160) -> Option<AccountRecord> {
+        let inner = self.0.lock().unwrap();
+        if &inner.hash != hash {
+            return None;
+        }
+        inner.accounts.get(address).cloned()
+    }
+
+    pub fn set_account(&self, hash: &SH256, address: &SH160, account: &AccountRecord) {
+        let mut inner = self.0.lock().unwrap();
+        if &inner.hash != hash {
+            return;
+        }
+        inner.accounts.insert(*address, account.clone());
+        inner.grow();
+    }
+
+    pub fn get_storage(&self, hash: &SH256, address: &SH160, key: &SH256) -> Option<SH256> {
+        let inner = self.0.lock().unwrap();
+        if &inner.hash != hash {
+            return None;
+        }
+        inner.storages.get(address)?.get(key).cloned()
+    }
+
+    pub fn set_storage(&self, hash: &SH256, address: &SH160, key: &SH256, value: SH256) {
+        let mut inner = self.0.lock().unwrap();
+        if &inner.hash != hash {
+            return;
+        }
+        inner
+            .storages
+            .entry(*address)
+            .or_default()
+            .insert(*key, value);
+        inner.grow();
+    }
+
+    pub fn get_code(&self, hash: &SH256, address: &SH160) -> Option<HexBytes> {
+        let inner = self.0.lock().unwrap();
+        if &inner.hash != hash {
+            return None;
+        }
+        inner.codes.get(address).cloned()
+    }
+
+    pub fn set_code(&self, hash: &SH256, address: &SH160, code: &HexBytes) {
+        let mut inner = self.0.lock().unwrap();
+        if &inner.hash != hash {
+            return;
+        }
+        inner.codes.insert(*address, code.clone());
+        inner.grow();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use eth_types::H256;
+
+    #[test]
+    fn test_state_cache() {
+        glog::init_test();
+        let hash = SH256::from(H256::from([0x01; 32]));
+        let other_hash = SH256::from(H256::from([0x02; 32]));
+        let beacon_roots = H160::from(BEACON_ROOTS_ADDRESS).into();
+        let addr = SH160::from(H160::from([0x11; 20]));
+        let key = SH256::from(H256::from([0x22; 32]));
+        let value = SH256::from(H256::from([0x33; 32]));
+
+        let cache = StateCache::new(0);
+        cache.0.lock().unwrap().hash = hash;
+        cache.set_storage(&hash, &addr, &key, value);
+        cache.set_storage(&hash, &beacon_roots, &key, value);
+        // the writes of the other blocks are ignored
+        cache.set_code(&other_hash, &addr, &HexBytes::from(vec![0x60]));
+        assert_eq!(cache.get_storage(&hash, &addr, &key), Some(value));
+        assert_eq!(cache.get_storage(&other_hash, &addr, &key), None);
+        assert_eq!(cache.get_code(&hash, &addr), None);
+
+        // the system contracts are always dropped when moving to the next block
+        cache.0.lock().unwrap().invalidate(&[], &[]);
+        assert_eq!(cache.get_storage(&hash, &addr, &key), Some(value));
+        assert_eq!(cache.get_storage(&hash, &beacon_roots, &key), None);
+    }
+}
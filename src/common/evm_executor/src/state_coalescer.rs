@@ -0,0 +1,171 @@
+use std::prelude::v1::*;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use base::format::debug;
+use eth_client::ExecutionClient;
+use eth_types::{AccountResult, BlockSelector, HexBytes, SH160, SH256};
+
+// shares the remote reads of a block between the fetchers running concurrently. the same
+// request is only sent once while it's in flight, and the requests issued within `window`
+// are sent together in one multi_rpc call.
+#[derive(Clone, Debug)]
+pub struct StateCoalescer {
+    client: Arc<ExecutionClient>,
+    number: u64,
+    window: Duration,
+    state: Arc<Mutex<CoalescerState>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FetchKey {
+    Storage(SH160, SH256),
+    AccountProof(SH160),
+    StorageProof(SH160, SH256),
+}
+
+#[derive(Clone, Debug)]
+pub enum FetchValue {
+    Storage(SH256),
+    Proof(Vec<HexBytes>),
+}
+
+#[derive(Debug, Default)]
+struct CoalescerState {
+    inflight: BTreeMap<FetchKey, Arc<FetchSlot>>,
+    // the requests waiting for the current batch
+    pending: Vec<FetchKey>,
+    // someone is collecting the pending requests
+    collecting: bool,
+}
+
+#[derive(Debug, Default)]
+struct FetchSlot {
+    result: Mutex<Option<Result<FetchValue, String>>>,
+    cond: Condvar,
+}
+
+impl FetchSlot {
+    fn wait(&self) -> Result<FetchValue, String> {
+        let mut result = self.result.lock().unwrap();
+        loop {
+            match &*result {
+                Some(result) => return result.clone(),
+                None => result = self.cond.wait(result).unwrap(),
+            }
+        }
+    }
+
+    fn set(&self, result: Result<FetchValue, String>) {
+        *self.result.lock().unwrap() = Some(result);
+        self.cond.notify_all();
+    }
+}
+
+impl StateCoalescer {
+    pub fn new(client: Arc<ExecutionClient>, number: u64, window: Duration) -> Self {
+        Self {
+            client,
+            number,
+            window,
+            state: Default::default(),
+        }
+    }
+
+    pub fn number(&self) -> u64 {
+        self.number
+    }
+
+    pub fn get(&self, key: FetchKey) -> Result<FetchValue, String> {
+        let (slot, leader) = {
+            let mut state = self.state.lock().unwrap();
+            if let Some(slot) = state.inflight.get(&key) {
+                let slot = slot.clone();
+                drop(state);
+                return slot.wait();
+            }
+            let slot = Arc::new(FetchSlot::default());
+            state.inflight.insert(key.clone(), slot.clone());
+            state.pending.push(key);
+            let leader = !state.collecting;
+            state.collecting = true;
+            (slot, leader)
+        };
+
+        if leader {
+            // waits for the other requests
+            std::thread::sleep(self.window);
+            let keys = {
+                let mut state = self.state.lock().unwrap();
+                state.collecting = false;
+                std::mem::take(&mut state.pending)
+            };
+            let result = self.fetch(&keys);
+
+            let mut state = self.state.lock().unwrap();
+            for (idx, key) in keys.iter().enumerate() {
+                let slot = match state.inflight.remove(key) {
+                    Some(slot) => slot,
+                    None => continue,
+                };
+                match &result {
+                    Ok(values) => slot.set(Ok(values[idx].clone())),
+                    Err(err) => slot.set(Err(err.clone())),
+                }
+            }
+        }
+        slot.wait()
+    }
+
+    fn fetch(&self, keys: &[FetchKey]) -> Result<Vec<FetchValue>, String> {
+        let client = self.client.raw();
+        let blk: BlockSelector = self.number.into();
+        let mut request = Vec::with_capacity(keys.len());
+        for key in keys {
+            let req = match key {
+                FetchKey::Storage(address, key) => {
+                    client.req("eth_getStorageAt", &(address, key, blk))
+                }
+                FetchKey::AccountProof(address) => {
+                    client.req("eth_getProof", &(address, Vec::<SH256>::new(), blk))
+                }
+                FetchKey::StorageProof(address, key) => {
+                    client.req("eth_getProof", &(address, [key], blk))
+                }
+            };
+            request.push(req.map_err(debug)?);
+        }
+        let response = client.multi_rpc(request).map_err(debug)?;
+        if response.len() != keys.len() {
+            return Err(format!(
+                "unexpected response: want {} items, got {}",
+                keys.len(),
+                response.len()
+            ));
+        }
+
+        let mut out = Vec::with_capacity(keys.len());
+        for (key, item) in keys.iter().zip(response.iter()) {
+            let value = match key {
+                FetchKey::Storage(..) => {
+                    FetchValue::Storage(serde_json::from_raw_value(item).map_err(debug)?)
+                }
+                FetchKey::AccountProof(_) => {
+                    let result: AccountResult = serde_json::from_raw_value(item).map_err(debug)?;
+                    FetchValue::Proof(result.account_proof)
+                }
+                FetchKey::StorageProof(..) => {
+                    let result: AccountResult = serde_json::from_raw_value(item).map_err(debug)?;
+                    match result.storage_proof.into_iter().next() {
+                        Some(storage) => FetchValue::Proof(storage.proof),
+                        None => return Err("storage proof not found".into()),
+                    }
+                }
+            };
+            out.push(value);
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,168 @@
+use std::prelude::v1::*;
+
+use eth_types::{HexBytes, SH160, SH256, SU256};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Change<T> {
+    pub from: T,
+    pub to: T,
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct AccountDiff {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance: Option<Change<SU256>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<Change<u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<Change<HexBytes>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<SH256, Change<SH256>>,
+}
+
+impl AccountDiff {
+    pub fn is_empty(&self) -> bool {
+        self.balance.is_none()
+            && self.nonce.is_none()
+            && self.code.is_none()
+            && self.storage.is_empty()
+    }
+}
+
+// the changes of the accounts touched by a tx, an account may be recorded more than once
+// (e.g. the evm changes and the gas payment), the first `from` is kept.
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+#[serde(transparent)]
+pub struct StateDiff(pub BTreeMap<SH160, AccountDiff>);
+
+impl StateDiff {
+    pub fn record_balance(&mut self, address: SH160, from: SU256, to: SU256) {
+        let acc = self.0.entry(address).or_default();
+        acc.balance = merge(acc.balance.take(), from, to);
+        self.clean(&address);
+    }
+
+    pub fn record_nonce(&mut self, address: SH160, from: u64, to: u64) {
+        let acc = self.0.entry(address).or_default();
+        acc.nonce = merge(acc.nonce.take(), from, to);
+        self.clean(&address);
+    }
+
+    pub fn record_code(&mut self, address: SH160, from: HexBytes, to: HexBytes) {
+        let acc = self.0.entry(address).or_default();
+        acc.code = merge(acc.code.take(), from, to);
+        self.clean(&address);
+    }
+
+    pub fn record_storage(&mut self, address: SH160, key: SH256, from: SH256, to: SH256) {
+        let acc = self.0.entry(address).or_default();
+        if let Some(change) = merge(acc.storage.remove(&key), from, to) {
+            acc.storage.insert(key, change);
+        }
+        self.clean(&address);
+    }
+
+    // folds the diff of a later tx into this one, e.g. the txs of a bundle
+    pub fn extend(&mut self, other: StateDiff) {
+        for (address, acc) in other.0 {
+            if let Some(change) = acc.balance {
+                self.record_balance(address, change.from, change.to);
+            }
+            if let Some(change) = acc.nonce {
+                self.record_nonce(address, change.from, change.to);
+            }
+            if let Some(change) = acc.code {
+                self.record_code(address, change.from, change.to);
+            }
+            for (key, change) in acc.storage {
+                self.record_storage(address, key, change.from, change.to);
+            }
+        }
+    }
+
+    fn clean(&mut self, address: &SH160) {
+        if matches!(self.0.get(address), Some(acc) if acc.is_empty()) {
+            self.0.remove(address);
+        }
+    }
+}
+
+fn merge<T: PartialEq>(old: Option<Change<T>>, from: T, to: T) -> Option<Change<T>> {
+    let from = match old {
+        Some(old) => old.from,
+        None => from,
+    };
+    (from != to).then(|| Change { from, to })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use eth_types::{H160, H256};
+
+    #[test]
+    fn test_merge() {
+        glog::init_test();
+        let addr = SH160::from(H160::from([0x11; 20]));
+        let key = SH256::from(H256::from([0x22; 32]));
+        let slot = |n: u8| SH256::from(H256::from([n; 32]));
+        let wei = |n: u64| SU256::from(n);
+
+        let mut diff = StateDiff::default();
+        diff.record_balance(addr, wei(100), wei(90));
+        // the gas payment is recorded after the evm changes, the first `from` is kept
+        diff.record_balance(addr, wei(90), wei(70));
+        diff.record_nonce(addr, 1, 2);
+        diff.record_storage(addr, key, slot(1), slot(2));
+        diff.record_storage(addr, key, slot(2), slot(3));
+        let acc = &diff.0[&addr];
+        assert_eq!(
+            acc.balance,
+            Some(Change {
+                from: wei(100),
+                to: wei(70)
+            })
+        );
+        assert_eq!(acc.nonce, Some(Change { from: 1, to: 2 }));
+        assert_eq!(
+            acc.storage[&key],
+            Change {
+                from: slot(1),
+                to: slot(3)
+            }
+        );
+
+        // the changes which are reverted are dropped, and so is the account without changes
+        diff.record_storage(addr, key, slot(3), slot(1));
+        assert!(diff.0[&addr].storage.is_empty());
+        diff.record_balance(addr, wei(70), wei(100));
+        diff.record_nonce(addr, 2, 1);
+        assert_eq!(diff, StateDiff::default());
+    }
+
+    #[test]
+    fn test_extend() {
+        glog::init_test();
+        let addr = SH160::from(H160::from([0x11; 20]));
+        let other = SH160::from(H160::from([0x33; 20]));
+        let wei = |n: u64| SU256::from(n);
+
+        let mut first = StateDiff::default();
+        first.record_nonce(addr, 1, 2);
+        let mut second = StateDiff::default();
+        second.record_nonce(addr, 2, 3);
+        second.record_balance(other, wei(0), wei(5));
+
+        first.extend(second);
+        assert_eq!(first.0[&addr].nonce, Some(Change { from: 1, to: 3 }));
+        assert_eq!(
+            first.0[&other].balance,
+            Some(Change {
+                from: wei(0),
+                to: wei(5)
+            })
+        );
+    }
+}
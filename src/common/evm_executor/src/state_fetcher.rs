@@ -1,62 +1,262 @@
 use std::prelude::v1::*;
 
-use std::sync::Arc;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
 
+use super::{
+    decode_account, decode_storage, verify_account_proof, verify_storage_proof, walk_trie,
+    FetchKey, FetchValue, ProofError, ProvedAccount, StateBackend, StateBackends, StateCache,
+    StateCoalescer, EMPTY_CODE_HASH,
+};
 use base::format::debug;
 use base::trace::AvgCounter;
+use crypto::keccak_hash;
 use eth_client::ExecutionClient;
 use eth_types::{
     BlockSelector, FetchState, FetchStateResult, HexBytes, TransactionAccessTuple, H160, H256,
-    SH160, SH256, SU256,
+    SH160, SH256, SU256, U256,
 };
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 
 #[derive(Clone, Debug)]
 pub struct BlockStateFetcher {
-    client: Arc<ExecutionClient>,
+    source: StateSource,
     blk: BlockSelector,
     acc: Option<SH160>,
     counter: AvgCounter,
+    recorder: Option<StateRecorder>,
+    // the cache and the hash of the block we read from
+    cache: Option<(StateCache, SH256)>,
+    coalescer: Option<StateCoalescer>,
+    // the state root of the block we read from, every read is verified against it with proofs
+    verify: Option<SH256>,
+    // the state root of the block we read from, the trie is walked from it with debug_dbGet
+    state_root: Option<SH256>,
+    backends: StateBackends,
+}
+
+#[derive(Clone, Debug)]
+enum StateSource {
+    Remote(Arc<ExecutionClient>),
+    // serves all the reads from a recording, without touching the network
+    Replay(Arc<StateRecord>),
 }
 
 impl BlockStateFetcher {
     pub fn new(client: Arc<ExecutionClient>, blk: BlockSelector) -> BlockStateFetcher {
         Self {
-            client,
+            source: StateSource::Remote(client),
             acc: None,
             blk,
             counter: AvgCounter::new(),
+            recorder: None,
+            cache: None,
+            coalescer: None,
+            verify: None,
+            state_root: None,
+            backends: StateBackends::default(),
         }
     }
-}
 
-impl statedb::StateFetcher for BlockStateFetcher {
-    fn with_acc(&self, address: &SH160) -> Self {
+    pub fn replay(record: Arc<StateRecord>, blk: BlockSelector) -> BlockStateFetcher {
         Self {
-            client: self.client.clone(),
-            blk: self.blk.clone(),
-            acc: Some(address.clone()),
-            counter: self.counter.clone(),
+            source: StateSource::Replay(record),
+            acc: None,
+            blk,
+            counter: AvgCounter::new(),
+            recorder: None,
+            cache: None,
+            coalescer: None,
+            verify: None,
+            state_root: None,
+            backends: StateBackends::default(),
         }
     }
 
-    fn fork(&self) -> Self {
-        self.clone()
+    // records every state read, including the ones from the forks.
+    pub fn with_recorder(mut self, recorder: StateRecorder) -> Self {
+        self.recorder = Some(recorder);
+        self
     }
 
-    fn get_block_hash(&self, number: u64) -> Result<SH256, statedb::Error> {
-        let _counter = self.counter.place();
+    // serves the account, storage and code reads from the cache, `hash` should be the same
+    // as the block we read from. the cache should be advanced to that block first.
+    pub fn with_cache(mut self, cache: StateCache, hash: SH256) -> Self {
+        self.cache = Some((cache, hash));
+        self
+    }
 
-        let header = self
-            .client
-            .get_block_header(number.into())
-            .map_err(|err| statedb::Error::CallRemoteFail(format!("[get_block_hash] {:?}", err)))?;
-        Ok(header.hash())
+    // shares the storage and proof requests with the other fetchers of the same block
+    pub fn with_coalescer(mut self, coalescer: StateCoalescer) -> Self {
+        self.coalescer = Some(coalescer);
+        self
     }
 
-    fn get_account(&self, address: &SH160) -> Result<(SU256, u64, HexBytes), statedb::Error> {
-        let _counter = self.counter.place();
+    // doesn't trust the remote, reads the states with proofs and checks them against the
+    // state root of the block we read from.
+    pub fn with_verify(mut self, state_root: SH256) -> Self {
+        self.verify = Some(state_root);
+        self
+    }
+
+    // allows to read the accounts, the storages and the proofs by walking the trie from the state
+    // root of the block we read from.
+    pub fn with_state_root(mut self, state_root: SH256) -> Self {
+        self.state_root = Some(state_root);
+        self
+    }
 
+    // reads the states with the backends supported by the remote, see StateBackends::probe.
+    // the misses are counted separately from the other fetchers.
+    pub fn with_backends(mut self, backends: &StateBackends) -> Self {
+        self.backends = backends.fork();
+        self
+    }
+
+    // returns and resets the number of the misses served by each backend
+    pub fn backend_usage(&self) -> BTreeMap<StateBackend, u64> {
+        self.backends.take_usage()
+    }
+
+    // serves the miss with the candidates in the configured order, falls back to the next one if
+    // the remote fails. a proof mismatch is returned at once, the remote can't be trusted.
+    fn serve<T, F>(&self, candidates: &[StateBackend], mut f: F) -> Result<T, statedb::Error>
+    where
+        F: FnMut(StateBackend) -> Result<T, statedb::Error>,
+    {
+        let backends = self
+            .backends
+            .select(candidates)
+            .map_err(statedb::Error::CallRemoteFail)?;
+        let mut last_err = None;
+        for backend in backends {
+            match f(backend) {
+                Ok(value) => {
+                    self.backends.served(backend);
+                    return Ok(value);
+                }
+                Err(err) if ProofError::from_state_error(&err).is_some() => return Err(err),
+                Err(err) => {
+                    glog::warn!("read states with {:?} fail: {:?}", backend, err);
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(last_err.unwrap())
+    }
+
+    fn state_root(&self) -> Option<&SH256> {
+        self.verify.as_ref().or(self.state_root.as_ref())
+    }
+
+    // the backends an account or a storage miss can be served with. the trie is walked (DbGet)
+    // and the proofs are checked (Proof) from the state root, so they need it to be known, and
+    // only they are allowed when verifying.
+    fn read_backends(&self) -> &'static [StateBackend] {
+        match (self.state_root().is_some(), self.verify.is_some()) {
+            (_, true) => &[StateBackend::DbGet, StateBackend::Proof],
+            (true, false) => &[
+                StateBackend::DbGet,
+                StateBackend::Proof,
+                StateBackend::Plain,
+            ],
+            (false, false) => &[StateBackend::Plain],
+        }
+    }
+
+    // the backends a proof miss can be served with, the proof is collected by walking the trie
+    // (DbGet) or read with eth_getProof.
+    fn proof_backends(&self) -> &'static [StateBackend] {
+        match self.state_root().is_some() {
+            true => &[StateBackend::DbGet, StateBackend::Proof],
+            false => &[StateBackend::Proof],
+        }
+    }
+
+    // walks the trie with debug_dbGet, returns the value and the nodes on the path as the proof
+    fn walk_nodes(
+        &self,
+        client: &ExecutionClient,
+        root: &SH256,
+        path: &[u8; 32],
+    ) -> Result<(Option<Vec<u8>>, Vec<HexBytes>), statedb::Error> {
+        let mut proof = Vec::new();
+        let value = walk_trie(root, path, |hash| {
+            let node = client
+                .get_dbnodes(&[*hash])
+                .map_err(|err| statedb::Error::CallRemoteFail(format!("{:?}", err)))?
+                .pop()
+                .unwrap_or_else(HexBytes::new);
+            if node.is_empty() {
+                // e.g. the node is pruned, or the remote stores the trie by path
+                return Err(statedb::Error::CallRemoteFail(format!(
+                    "[dbget] node not found: {:?}",
+                    hash
+                )));
+            }
+            proof.push(node.clone());
+            Ok(node)
+        })?;
+        Ok((value, proof))
+    }
+
+    fn get_account_by_nodes(
+        &self,
+        client: &ExecutionClient,
+        state_root: &SH256,
+        address: &SH160,
+    ) -> Result<AccountRecord, statedb::Error> {
+        let (value, _) = self.walk_nodes(client, state_root, &keccak_hash(address.as_bytes()))?;
+        let account = match value {
+            Some(value) => decode_account(&value)?,
+            None => ProvedAccount::empty(),
+        };
+        self.proved_record(client, address, account)
+    }
+
+    fn get_storage_by_nodes(
+        &self,
+        client: &ExecutionClient,
+        state_root: &SH256,
+        address: &SH160,
+        key: &SH256,
+    ) -> Result<(SH256, Vec<HexBytes>), statedb::Error> {
+        let (account, _) = self.walk_nodes(client, state_root, &keccak_hash(address.as_bytes()))?;
+        let storage_root = match account {
+            Some(account) => decode_account(&account)?.storage_root,
+            None => return Ok((SH256::default(), Vec::new())),
+        };
+        let (value, proof) =
+            self.walk_nodes(client, &storage_root, &keccak_hash(key.as_bytes()))?;
+        let value = match value {
+            Some(value) => decode_storage(&value)?,
+            None => SH256::default(),
+        };
+        Ok((value, proof))
+    }
+
+    // collects the proof of the account, or the slot of `self.acc` by walking the trie
+    fn get_proof_by_nodes(
+        &self,
+        client: &ExecutionClient,
+        state_root: &SH256,
+        key: &[u8],
+    ) -> Result<Vec<HexBytes>, statedb::Error> {
+        match &self.acc {
+            Some(acc) => {
+                let key = H256::from_slice(key).into();
+                Ok(self.get_storage_by_nodes(client, state_root, acc, &key)?.1)
+            }
+            None => Ok(self.walk_nodes(client, state_root, &keccak_hash(key))?.1),
+        }
+    }
+
+    fn get_plain_account(
+        &self,
+        client: &ExecutionClient,
+        address: &SH160,
+    ) -> Result<AccountRecord, statedb::Error> {
         let fetch_state = FetchState {
             access_list: Some(Cow::Owned(TransactionAccessTuple {
                 address: address.clone(),
@@ -64,32 +264,415 @@ impl statedb::StateFetcher for BlockStateFetcher {
             })),
             code: Some(address.clone()),
         };
-        let result = self
-            .client
+        let result = client
             .fetch_states(&[fetch_state], self.blk, false)
             .map_err(|err| statedb::Error::CallRemoteFail(format!("{:?}", err)))?
             .pop()
             .unwrap();
         let acc = result.acc.unwrap();
-        Ok((acc.balance, acc.nonce.as_u64(), result.code.unwrap()))
+        Ok(AccountRecord {
+            balance: acc.balance,
+            nonce: acc.nonce.as_u64(),
+            code: result.code.unwrap(),
+        })
     }
 
-    fn get_storage(&self, address: &SH160, key: &SH256) -> Result<SH256, statedb::Error> {
+    fn get_plain_storage(
+        &self,
+        client: &ExecutionClient,
+        address: &SH160,
+        key: &SH256,
+    ) -> Result<SH256, statedb::Error> {
+        match &self.coalescer {
+            Some(coalescer) => match coalescer.get(FetchKey::Storage(*address, *key)) {
+                Ok(FetchValue::Storage(value)) => Ok(value),
+                Ok(value) => unreachable!("unexpected value: {:?}", value),
+                Err(err) => Err(statedb::Error::CallRemoteFail(err)),
+            },
+            None => client
+                .get_storage(address, key, self.blk)
+                .map_err(|err| statedb::Error::CallRemoteFail(format!("{:?}", err))),
+        }
+    }
+
+    // reads the proof of the account, or the slot of `self.acc` with eth_getProof
+    fn get_proof_by_rpc(
+        &self,
+        client: &ExecutionClient,
+        key: &[u8],
+    ) -> Result<Vec<HexBytes>, statedb::Error> {
+        if let Some(state_root) = &self.verify {
+            return match &self.acc {
+                Some(acc) => {
+                    let key = H256::from_slice(key).into();
+                    Ok(self.get_verified_storage(client, state_root, acc, &key)?.1)
+                }
+                None => {
+                    let account = H160::from_slice(key).into();
+                    let result = client
+                        .get_proof(&account, &[], self.blk)
+                        .map_err(|err| statedb::Error::CallRemoteFail(format!("{:?}", err)))?;
+                    verify_account_proof(state_root, key, &result.account_proof)?;
+                    Ok(result.account_proof)
+                }
+            };
+        }
+        if let Some(coalescer) = &self.coalescer {
+            let fetch_key = match &self.acc {
+                Some(acc) => FetchKey::StorageProof(*acc, H256::from_slice(key).into()),
+                None => FetchKey::AccountProof(H160::from_slice(key).into()),
+            };
+            return match coalescer.get(fetch_key) {
+                Ok(FetchValue::Proof(proof)) => Ok(proof),
+                Ok(value) => unreachable!("unexpected value: {:?}", value),
+                Err(err) => Err(statedb::Error::CallRemoteFail(err)),
+            };
+        }
+        match &self.acc {
+            Some(acc) => {
+                assert_eq!(key.len(), 32);
+                let key = H256::from_slice(key).into();
+                let result = client
+                    .get_proof(acc, &[key], self.blk)
+                    .map_err(|err| statedb::Error::CallRemoteFail(format!("{:?}", err)))?;
+                let storage = result.storage_proof.into_iter().next().unwrap();
+                Ok(storage.proof)
+            }
+            None => {
+                assert_eq!(key.len(), 20);
+                let account = H160::from_slice(key).into();
+                let result = client
+                    .get_proof(&account, &[], self.blk)
+                    .map_err(|err| statedb::Error::CallRemoteFail(format!("{:?}", err)))?;
+                Ok(result.account_proof)
+            }
+        }
+    }
+
+    fn get_verified_account(
+        &self,
+        client: &ExecutionClient,
+        state_root: &SH256,
+        address: &SH160,
+    ) -> Result<AccountRecord, statedb::Error> {
+        let result = client
+            .get_proof(address, &[], self.blk)
+            .map_err(|err| statedb::Error::CallRemoteFail(format!("{:?}", err)))?;
+        let account = verify_account_proof(state_root, address.as_bytes(), &result.account_proof)?;
+        self.proved_record(client, address, account)
+    }
+
+    // the balance and the nonce are taken from the proved account, the code is checked against
+    // its code hash.
+    fn proved_record(
+        &self,
+        client: &ExecutionClient,
+        address: &SH160,
+        account: ProvedAccount,
+    ) -> Result<AccountRecord, statedb::Error> {
+        let code = match account.code_hash.as_bytes() == EMPTY_CODE_HASH {
+            true => HexBytes::new(),
+            false => {
+                let code = client
+                    .get_code(address, self.blk)
+                    .map_err(|err| statedb::Error::CallRemoteFail(format!("{:?}", err)))?;
+                Self::verify_code(&account, &code)?;
+                code
+            }
+        };
+        Ok(AccountRecord {
+            balance: account.balance.into(),
+            nonce: account.nonce,
+            code,
+        })
+    }
+
+    fn get_verified_storage(
+        &self,
+        client: &ExecutionClient,
+        state_root: &SH256,
+        address: &SH160,
+        key: &SH256,
+    ) -> Result<(SH256, Vec<HexBytes>), statedb::Error> {
+        let result = client
+            .get_proof(address, &[*key], self.blk)
+            .map_err(|err| statedb::Error::CallRemoteFail(format!("{:?}", err)))?;
+        let account = verify_account_proof(state_root, address.as_bytes(), &result.account_proof)?;
+        let proof = match result.storage_proof.into_iter().next() {
+            Some(storage) => storage.proof,
+            None => return Err(ProofError::from("storage proof not found").into()),
+        };
+        let value = verify_storage_proof(&account.storage_root, key, &proof)?;
+        Ok((value, proof))
+    }
+
+    fn verify_code(account: &ProvedAccount, code: &HexBytes) -> Result<(), statedb::Error> {
+        if keccak_hash(code) != account.code_hash.as_bytes() {
+            let err = format!("code hash mismatch, want {:?}", account.code_hash);
+            return Err(ProofError(err).into());
+        }
+        Ok(())
+    }
+
+    // checks the result of fetch_states_with_proof and replaces the values with the proved ones
+    fn verify_states(
+        &self,
+        client: &ExecutionClient,
+        state_root: &SH256,
+        list: &[FetchState],
+        result: &mut [FetchStateResult],
+    ) -> Result<(), statedb::Error> {
+        for (item, state) in list.iter().zip(result.iter_mut()) {
+            let address = match item.get_addr() {
+                Some(address) => address.clone(),
+                None => continue,
+            };
+            let account = match (&item.access_list, &mut state.acc) {
+                (Some(access), Some(acc)) => {
+                    if acc.storage_proof.len() != access.storage_keys.len() {
+                        return Err(ProofError::from("storage proof not found").into());
+                    }
+                    let account =
+                        verify_account_proof(state_root, address.as_bytes(), &acc.account_proof)?;
+                    acc.balance = account.balance.into();
+                    acc.nonce = account.nonce.into();
+                    for (key, storage) in
+                        access.storage_keys.iter().zip(acc.storage_proof.iter_mut())
+                    {
+                        let value =
+                            verify_storage_proof(&account.storage_root, key, &storage.proof)?;
+                        storage.value = U256::from_big_endian(value.as_bytes()).into();
+                    }
+                    account
+                }
+                _ => {
+                    let proof = client
+                        .get_proof(&address, &[], self.blk)
+                        .map_err(|err| statedb::Error::CallRemoteFail(format!("{:?}", err)))?;
+                    verify_account_proof(state_root, address.as_bytes(), &proof.account_proof)?
+                }
+            };
+            if let Some(code) = &state.code {
+                Self::verify_code(&account, code)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn record<F>(&self, f: F)
+    where
+        F: FnOnce(&mut StateRecord),
+    {
+        if let Some(recorder) = &self.recorder {
+            f(&mut recorder.0.lock().unwrap());
+        }
+    }
+
+    fn proof_key(&self, key: &[u8]) -> String {
+        format!("{:?}:{}", self.acc, HexBytes::from(key))
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StateRecord {
+    pub block_hashes: BTreeMap<u64, SH256>,
+    pub accounts: BTreeMap<SH160, AccountRecord>,
+    pub storages: BTreeMap<SH160, BTreeMap<SH256, SH256>>,
+    pub codes: BTreeMap<SH160, HexBytes>,
+    // keyed by the json of the request
+    pub prefetches: BTreeMap<String, Vec<FetchStateResult>>,
+    pub proofs: BTreeMap<String, Vec<HexBytes>>,
+    pub nodes: BTreeMap<SH256, HexBytes>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AccountRecord {
+    pub balance: SU256,
+    pub nonce: u64,
+    pub code: HexBytes,
+}
+
+impl StateRecord {
+    fn missing<T: std::fmt::Debug>(kind: &str, key: T) -> statedb::Error {
+        statedb::Error::CallRemoteFail(format!("[replay] {} not recorded: {:?}", kind, key))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct StateRecorder(Arc<Mutex<StateRecord>>);
+
+impl StateRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> StateRecord {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl statedb::StateFetcher for BlockStateFetcher {
+    fn with_acc(&self, address: &SH160) -> Self {
+        Self {
+            source: self.source.clone(),
+            blk: self.blk.clone(),
+            acc: Some(address.clone()),
+            counter: self.counter.clone(),
+            recorder: self.recorder.clone(),
+            cache: self.cache.clone(),
+            coalescer: self.coalescer.clone(),
+            verify: self.verify,
+            state_root: self.state_root,
+            backends: self.backends.clone(),
+        }
+    }
+
+    fn fork(&self) -> Self {
+        self.clone()
+    }
+
+    fn get_block_hash(&self, number: u64) -> Result<SH256, statedb::Error> {
         let _counter = self.counter.place();
 
-        Ok(self
-            .client
-            .get_storage(address, key, self.blk)
-            .map_err(|err| statedb::Error::CallRemoteFail(format!("{:?}", err)))?)
+        let hash = match &self.source {
+            StateSource::Remote(client) => {
+                let header = client.get_block_header(number.into()).map_err(|err| {
+                    statedb::Error::CallRemoteFail(format!("[get_block_hash] {:?}", err))
+                })?;
+                header.hash()
+            }
+            StateSource::Replay(record) => match record.block_hashes.get(&number) {
+                Some(hash) => *hash,
+                None => return Err(StateRecord::missing("block hash", number)),
+            },
+        };
+        self.record(|r| {
+            r.block_hashes.insert(number, hash);
+        });
+        Ok(hash)
+    }
+
+    fn get_account(&self, address: &SH160) -> Result<(SU256, u64, HexBytes), statedb::Error> {
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|(cache, hash)| cache.get_account(hash, address));
+        let _counter = cached.is_none().then(|| self.counter.place());
+
+        let account = match (cached, &self.source) {
+            (Some(account), _) => account,
+            (None, StateSource::Remote(client)) => {
+                let account = self.serve(self.read_backends(), |backend| match backend {
+                    StateBackend::DbGet => {
+                        let state_root = self.state_root().unwrap();
+                        self.get_account_by_nodes(client, state_root, address)
+                    }
+                    StateBackend::Proof => {
+                        let state_root = self.state_root().unwrap();
+                        self.get_verified_account(client, state_root, address)
+                    }
+                    StateBackend::Plain => self.get_plain_account(client, address),
+                })?;
+                if let Some((cache, hash)) = &self.cache {
+                    cache.set_account(hash, address, &account);
+                }
+                account
+            }
+            (None, StateSource::Replay(record)) => match record.accounts.get(address) {
+                Some(account) => account.clone(),
+                None => return Err(StateRecord::missing("account", address)),
+            },
+        };
+        self.record(|r| {
+            r.accounts.insert(*address, account.clone());
+        });
+        Ok((account.balance, account.nonce, account.code))
+    }
+
+    fn get_storage(&self, address: &SH160, key: &SH256) -> Result<SH256, statedb::Error> {
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|(cache, hash)| cache.get_storage(hash, address, key));
+        let _counter = cached.is_none().then(|| self.counter.place());
+
+        let value = match (cached, &self.source) {
+            (Some(value), _) => value,
+            (None, StateSource::Remote(client)) => {
+                let value = self.serve(self.read_backends(), |backend| match backend {
+                    StateBackend::DbGet => {
+                        let state_root = self.state_root().unwrap();
+                        Ok(self
+                            .get_storage_by_nodes(client, state_root, address, key)?
+                            .0)
+                    }
+                    StateBackend::Proof => {
+                        let state_root = self.state_root().unwrap();
+                        Ok(self
+                            .get_verified_storage(client, state_root, address, key)?
+                            .0)
+                    }
+                    StateBackend::Plain => self.get_plain_storage(client, address, key),
+                })?;
+                if let Some((cache, hash)) = &self.cache {
+                    cache.set_storage(hash, address, key, value);
+                }
+                value
+            }
+            (None, StateSource::Replay(record)) => {
+                match record.storages.get(address).and_then(|n| n.get(key)) {
+                    Some(value) => *value,
+                    None => return Err(StateRecord::missing("storage", (address, key))),
+                }
+            }
+        };
+        self.record(|r| {
+            r.storages.entry(*address).or_default().insert(*key, value);
+        });
+        Ok(value)
     }
 
     fn get_code(&self, address: &SH160) -> Result<HexBytes, statedb::Error> {
-        let _counter = self.counter.place();
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|(cache, hash)| cache.get_code(hash, address));
+        let _counter = cached.is_none().then(|| self.counter.place());
 
-        let code = self
-            .client
-            .get_code(address, self.blk)
-            .map_err(|err| statedb::Error::CallRemoteFail(format!("[get_block_hash] {:?}", err)))?;
+        let code = match (cached, &self.source) {
+            (Some(code), _) => code,
+            (None, StateSource::Remote(client)) => {
+                // the code can only be read with eth_getCode, it's checked against the code hash
+                // in the proof when verifying.
+                let code = self.serve(&[StateBackend::Plain], |_| {
+                    client.get_code(address, self.blk).map_err(|err| {
+                        statedb::Error::CallRemoteFail(format!("[get_code] {:?}", err))
+                    })
+                })?;
+                if let Some(state_root) = &self.verify {
+                    let result = client
+                        .get_proof(address, &[], self.blk)
+                        .map_err(|err| statedb::Error::CallRemoteFail(format!("{:?}", err)))?;
+                    let account = verify_account_proof(
+                        state_root,
+                        address.as_bytes(),
+                        &result.account_proof,
+                    )?;
+                    Self::verify_code(&account, &code)?;
+                }
+                if let Some((cache, hash)) = &self.cache {
+                    cache.set_code(hash, address, &code);
+                }
+                code
+            }
+            (None, StateSource::Replay(record)) => match record.codes.get(address) {
+                Some(code) => code.clone(),
+                None => return Err(StateRecord::missing("code", address)),
+            },
+        };
+        self.record(|r| {
+            r.codes.insert(*address, code.clone());
+        });
         Ok(code)
     }
 
@@ -98,9 +681,43 @@ impl statedb::StateFetcher for BlockStateFetcher {
         list: &[FetchState],
         with_proof: bool,
     ) -> Result<Vec<FetchStateResult>, statedb::Error> {
-        self.client
-            .fetch_states(list, self.blk, with_proof)
-            .map_err(|err| statedb::Error::CallRemoteFail(format!("[get_block_hash] {:?}", err)))
+        let key = match (&self.source, &self.recorder) {
+            (StateSource::Remote(_), None) => String::new(),
+            _ => serde_json::to_string(&(list, with_proof))
+                .map_err(|err| statedb::Error::CallRemoteFail(format!("{:?}", err)))?,
+        };
+        let result = match &self.source {
+            StateSource::Remote(client) => {
+                // the proofs are required to verify the states, otherwise falls back to the
+                // plain reads if the remote doesn't serve the proofs.
+                let candidates: &[StateBackend] = match (with_proof, self.verify.is_some()) {
+                    (_, true) => &[StateBackend::Proof],
+                    (true, false) => &[StateBackend::Proof, StateBackend::Plain],
+                    (false, false) => &[StateBackend::Plain],
+                };
+                self.serve(candidates, |backend| {
+                    let with_proof = backend == StateBackend::Proof;
+                    let mut result =
+                        client
+                            .fetch_states(list, self.blk, with_proof)
+                            .map_err(|err| {
+                                statedb::Error::CallRemoteFail(format!("[prefetch] {:?}", err))
+                            })?;
+                    if let Some(state_root) = &self.verify {
+                        self.verify_states(client, state_root, list, &mut result)?;
+                    }
+                    Ok(result)
+                })?
+            }
+            StateSource::Replay(record) => match record.prefetches.get(&key) {
+                Some(result) => result.clone(),
+                None => return Err(StateRecord::missing("prefetch", &key)),
+            },
+        };
+        self.record(|r| {
+            r.prefetches.insert(key, result.clone());
+        });
+        Ok(result)
     }
 
     fn get_miss_usage(&self) -> base::trace::AvgCounterResult {
@@ -112,34 +729,65 @@ impl statedb::ProofFetcher for BlockStateFetcher {
     fn fetch_proofs(&self, key: &[u8]) -> Result<Vec<HexBytes>, String> {
         let _counter = self.counter.place();
         glog::debug!(exclude: "dry_run", target: "state_fetch", "fetch proof: acc[{:?}] {}", self.acc, HexBytes::from(key));
-        match &self.acc {
-            Some(acc) => {
-                assert_eq!(key.len(), 32);
-                let key = H256::from_slice(key).into();
-                let result = self
-                    .client
-                    .get_proof(acc, &[key], self.blk)
-                    .map_err(debug)?;
-                let storage = result.storage_proof.into_iter().next().unwrap();
-                Ok(storage.proof)
-            }
-            None => {
-                assert_eq!(key.len(), 20);
-                let account = H160::from_slice(key).into();
-                let result = self
-                    .client
-                    .get_proof(&account, &[], self.blk)
-                    .map_err(debug)?;
-                Ok(result.account_proof)
+        let proof = match &self.source {
+            StateSource::Remote(client) => self
+                .serve(self.proof_backends(), |backend| match backend {
+                    StateBackend::DbGet => {
+                        let state_root = self.state_root().unwrap();
+                        self.get_proof_by_nodes(client, state_root, key)
+                    }
+                    _ => self.get_proof_by_rpc(client, key),
+                })
+                .map_err(debug)?,
+            StateSource::Replay(record) => {
+                let proof_key = self.proof_key(key);
+                return match record.proofs.get(&proof_key) {
+                    Some(proof) => Ok(proof.clone()),
+                    None => Err(format!("{:?}", StateRecord::missing("proof", &proof_key))),
+                };
             }
-        }
+        };
+        self.record(|r| {
+            r.proofs.insert(self.proof_key(key), proof.clone());
+        });
+        Ok(proof)
     }
 
     fn get_nodes(&self, node: &[SH256]) -> Result<Vec<HexBytes>, String> {
         let _counter = self.counter.place();
 
-        self.client
-            .get_dbnodes(node)
-            .map_err(|err| format!("{:?}", err))
+        let nodes = match &self.source {
+            // the nodes can only be read by hash with debug_dbGet
+            StateSource::Remote(client) => self
+                .serve(&[StateBackend::DbGet], |_| {
+                    client
+                        .get_dbnodes(node)
+                        .map_err(|err| statedb::Error::CallRemoteFail(format!("{:?}", err)))
+                })
+                .map_err(debug)?,
+            StateSource::Replay(record) => {
+                let mut nodes = Vec::with_capacity(node.len());
+                for hash in node {
+                    match record.nodes.get(hash) {
+                        Some(n) => nodes.push(n.clone()),
+                        None => return Err(format!("{:?}", StateRecord::missing("node", hash))),
+                    }
+                }
+                nodes
+            }
+        };
+        if self.verify.is_some() {
+            for (hash, n) in node.iter().zip(nodes.iter()) {
+                if keccak_hash(n) != hash.as_bytes() {
+                    return Err(ProofError(format!("node hash mismatch: {:?}", hash)).to_string());
+                }
+            }
+        }
+        self.record(|r| {
+            for (hash, n) in node.iter().zip(nodes.iter()) {
+                r.nodes.insert(*hash, n.clone());
+            }
+        });
+        Ok(nodes)
     }
 }
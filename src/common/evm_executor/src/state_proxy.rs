@@ -2,14 +2,20 @@ use std::prelude::v1::*;
 
 use super::Context;
 use crypto::keccak_hash;
-use eth_types::{H160, H256, SH256, U256};
+use eth_types::{H160, H256, SH160, SH256, U256};
 use statedb::StateDB;
 
 use core::cell::RefCell;
+use std::collections::BTreeSet;
 
 pub struct StateProxy<'a, D: StateDB> {
     state_db: RefCell<&'a mut D>,
     ctx: Context<'a>,
+    // the backend can't interrupt the evm, so we keep the first error and return
+    // the default values until the execution exits.
+    err: RefCell<Option<statedb::Error>>,
+    // the storage slots read by the evm
+    reads: RefCell<BTreeSet<(SH160, SH256)>>,
 }
 
 impl<'a, D: StateDB> StateProxy<'a, D> {
@@ -17,6 +23,36 @@ impl<'a, D: StateDB> StateProxy<'a, D> {
         Self {
             state_db: RefCell::new(state),
             ctx,
+            err: RefCell::new(None),
+            reads: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    pub fn take_err(&self) -> Option<statedb::Error> {
+        self.err.borrow_mut().take()
+    }
+
+    pub fn take_reads(&self) -> BTreeSet<(SH160, SH256)> {
+        std::mem::take(&mut *self.reads.borrow_mut())
+    }
+
+    fn get_storage(&self, address: H160, index: H256) -> Result<SH256, statedb::Error> {
+        let (address, index) = (address.into(), index.into());
+        self.reads.borrow_mut().insert((address, index));
+        self.state_db.borrow_mut().get_state(&address, &index)
+    }
+
+    fn check<T: Default>(&self, name: &str, result: Result<T, statedb::Error>) -> T {
+        match result {
+            Ok(val) => val,
+            Err(err) => {
+                glog::error!("get {} fail: {:?}", name, err);
+                let mut last_err = self.err.borrow_mut();
+                if last_err.is_none() {
+                    *last_err = Some(err);
+                }
+                T::default()
+            }
         }
     }
 }
@@ -28,11 +64,11 @@ impl<'a, D: StateDB> evm::backend::Backend for StateProxy<'a, D> {
     }
 
     fn basic(&self, address: H160) -> evm::backend::Basic {
-        let (balance, nonce) = self
+        let result = self
             .state_db
             .borrow_mut()
-            .get_account_basic(&address.into())
-            .unwrap();
+            .get_account_basic(&address.into());
+        let (balance, nonce) = self.check("basic", result);
 
         glog::debug!(target: "executor", "get basic: {:?} => {},{}", address, balance, nonce);
         evm::backend::Basic {
@@ -63,12 +99,8 @@ impl<'a, D: StateDB> evm::backend::Backend for StateProxy<'a, D> {
             return Default::default();
         }
 
-        let val = self
-            .state_db
-            .borrow()
-            .get_block_hash(number.into())
-            .unwrap()
-            .into();
+        let result = self.state_db.borrow().get_block_hash(number.into());
+        let val = self.check("block hash", result).into();
 
         glog::debug!(target: "executor", "get block hash: {:?} => {:?}", number, val);
         val
@@ -90,18 +122,16 @@ impl<'a, D: StateDB> evm::backend::Backend for StateProxy<'a, D> {
     }
 
     fn code(&self, address: H160) -> Vec<u8> {
-        let code = self
-            .state_db
-            .borrow_mut()
-            .get_code(&address.into())
-            .unwrap();
+        let result = self.state_db.borrow_mut().get_code(&address.into());
+        let code = self.check("code", result);
 
         glog::debug!(target: "executor", "get code: {:?}, hash:{:?}", address, SH256::from(keccak_hash(&code)));
         code.as_ref().clone().into()
     }
 
     fn exists(&self, address: H160) -> bool {
-        let exists = self.state_db.borrow_mut().exist(&address.into()).unwrap();
+        let result = self.state_db.borrow_mut().exist(&address.into());
+        let exists = self.check("exists", result);
         glog::debug!(target: "executor", "get exists: {:?} => {:?}", address, exists);
         exists
     }
@@ -121,12 +151,8 @@ impl<'a, D: StateDB> evm::backend::Backend for StateProxy<'a, D> {
     }
 
     fn original_storage(&self, address: H160, index: H256) -> Option<H256> {
-        let val = self
-            .state_db
-            .borrow_mut()
-            .get_state(&address.into(), &index.into())
-            .unwrap()
-            .into();
+        let result = self.get_storage(address, index);
+        let val = self.check("storage", result).into();
         if val == H256::default() {
             return None;
         }
@@ -136,12 +162,8 @@ impl<'a, D: StateDB> evm::backend::Backend for StateProxy<'a, D> {
     }
 
     fn storage(&self, address: H160, index: H256) -> H256 {
-        let val = self
-            .state_db
-            .borrow_mut()
-            .get_state(&address.into(), &index.into())
-            .unwrap()
-            .into();
+        let result = self.get_storage(address, index);
+        let val = self.check("storage", result).into();
         glog::debug!(target: "executor", "get storage: {:?}.{:?} = {:?}", address, index, val);
         val
     }
@@ -0,0 +1,87 @@
+use std::prelude::v1::*;
+
+use eth_types::{H160, H256, SH160, SH256};
+use statedb::StateDB;
+
+// EIP-4788, keeps the parent beacon block root of the recent blocks
+pub const BEACON_ROOTS_ADDRESS: [u8; 20] = [
+    0x00, 0x0f, 0x3d, 0xf6, 0xd7, 0x32, 0x80, 0x7e, 0xf1, 0x31, 0x9f, 0xb7, 0xb8, 0xbb, 0x85, 0x22,
+    0xd0, 0xbe, 0xac, 0x02,
+];
+const HISTORY_BUFFER_LENGTH: u64 = 8191;
+
+// EIP-2935, keeps the hash of the recent blocks
+pub const HISTORY_STORAGE_ADDRESS: [u8; 20] = [
+    0x00, 0x00, 0xf9, 0x08, 0x27, 0xf1, 0xc5, 0x3a, 0x10, 0xcb, 0x7a, 0x02, 0x33, 0x5b, 0x17, 0x53,
+    0x20, 0x00, 0x29, 0x35,
+];
+const HISTORY_SERVE_WINDOW: u64 = 8191;
+
+// the system calls made before the txs of a block. the contracts only store their input, so
+// the slots are written directly instead of running the code. like the system call, nothing
+// happens if the contract is not deployed yet.
+pub fn apply_beacon_root<D: StateDB>(
+    state_db: &mut D,
+    timestamp: u64,
+    root: &SH256,
+) -> Result<(), statedb::Error> {
+    let address = H160::from(BEACON_ROOTS_ADDRESS).into();
+    if !is_deployed(state_db, &address)? {
+        return Ok(());
+    }
+    let (timestamp_slot, root_slot) = beacon_root_slots(timestamp);
+    state_db.set_state(&address, &timestamp_slot, u64_word(timestamp))?;
+    state_db.set_state(&address, &root_slot, *root)?;
+    Ok(())
+}
+
+// `number` is the block being executed, the hash of its parent is stored
+pub fn apply_parent_hash<D: StateDB>(
+    state_db: &mut D,
+    number: u64,
+    parent_hash: &SH256,
+) -> Result<(), statedb::Error> {
+    let address = H160::from(HISTORY_STORAGE_ADDRESS).into();
+    if number == 0 || !is_deployed(state_db, &address)? {
+        return Ok(());
+    }
+    state_db.set_state(&address, &parent_hash_slot(number), *parent_hash)?;
+    Ok(())
+}
+
+fn is_deployed<D: StateDB>(state_db: &mut D, address: &SH160) -> Result<bool, statedb::Error> {
+    Ok(state_db.get_code(address)?.len() > 0)
+}
+
+fn beacon_root_slots(timestamp: u64) -> (SH256, SH256) {
+    let idx = timestamp % HISTORY_BUFFER_LENGTH;
+    (u64_word(idx), u64_word(idx + HISTORY_BUFFER_LENGTH))
+}
+
+fn parent_hash_slot(number: u64) -> SH256 {
+    u64_word((number - 1) % HISTORY_SERVE_WINDOW)
+}
+
+fn u64_word(n: u64) -> SH256 {
+    H256::from_low_u64_be(n).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_system_contract_slots() {
+        glog::init_test();
+        let (timestamp_slot, root_slot) = beacon_root_slots(1710338135);
+        // 1710338135 % 8191 = 8189
+        assert_eq!(timestamp_slot, u64_word(8189));
+        assert_eq!(root_slot, u64_word(8189 + 8191));
+        assert_eq!(beacon_root_slots(8191), (u64_word(0), u64_word(8191)));
+
+        assert_eq!(parent_hash_slot(1), u64_word(0));
+        assert_eq!(parent_hash_slot(8191), u64_word(8190));
+        // the ring buffer wraps around
+        assert_eq!(parent_hash_slot(8192), u64_word(0));
+    }
+}
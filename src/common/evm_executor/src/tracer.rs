@@ -0,0 +1,804 @@
+use std::prelude::v1::*;
+
+use super::{Context, ExecuteResult};
+use eth_types::{HexBytes, H160, SH160, SH256, SU256, SU64};
+use serde::{Deserialize, Serialize};
+use statedb::StateDB;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+use evm::gasometer::tracing as gas_tracing;
+use evm::tracing as evm_tracing;
+use evm::{CreateScheme, ExitReason};
+
+// the `tracer` and `tracerConfig` of geth's debug_traceCall
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceConfig {
+    pub tracer: TracerKind,
+    #[serde(default)]
+    pub tracer_config: TracerConfig,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Eq, PartialEq)]
+pub enum TracerKind {
+    #[serde(rename = "callTracer")]
+    Call,
+    #[serde(rename = "prestateTracer")]
+    Prestate,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TracerConfig {
+    // callTracer: skips the internal calls
+    #[serde(default)]
+    pub only_top_call: bool,
+    // prestateTracer: returns the pre and post state of the changed accounts
+    #[serde(default)]
+    pub diff_mode: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum TraceResult {
+    Call(CallFrame),
+    Prestate(BTreeMap<SH160, AccountState>),
+    PrestateDiff {
+        pre: BTreeMap<SH160, AccountState>,
+        post: BTreeMap<SH160, AccountState>,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+    pub from: SH160,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<SH160>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<SU256>,
+    pub gas: SU64,
+    pub gas_used: SU64,
+    pub input: HexBytes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<HexBytes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert_reason: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<CallFrame>,
+}
+
+impl CallFrame {
+    fn exit(&mut self, reason: &ExitReason, output: &[u8]) {
+        if !output.is_empty() {
+            self.output = Some(output.to_vec().into());
+        }
+        match reason {
+            ExitReason::Succeed(_) => {}
+            ExitReason::Revert(_) => {
+                self.error = Some("execution reverted".into());
+                self.revert_reason = decode_revert_reason(output);
+            }
+            ExitReason::Error(err) => self.error = Some(format!("{:?}", err)),
+            ExitReason::Fatal(err) => self.error = Some(format!("{:?}", err)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct AccountState {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance: Option<SU256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<HexBytes>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<SH256, SH256>,
+}
+
+impl AccountState {
+    fn is_empty(&self) -> bool {
+        self.balance.unwrap_or_default().is_zero()
+            && self.nonce.unwrap_or_default() == 0
+            && self.code.is_none()
+            && self.storage.values().all(|n| n == &SH256::default())
+    }
+}
+
+#[derive(Debug)]
+pub enum Tracer {
+    Call(CallTracer),
+    Prestate(PrestateTracer),
+}
+
+impl Tracer {
+    pub fn new(cfg: &TraceConfig, ctx: &Context) -> Self {
+        match cfg.tracer {
+            TracerKind::Call => Self::Call(CallTracer::new(cfg.tracer_config.only_top_call, ctx)),
+            TracerKind::Prestate => Self::Prestate(PrestateTracer::new(
+                cfg.tracer_config.diff_mode,
+                *ctx.caller,
+                ctx.header.miner,
+            )),
+        }
+    }
+
+    // called before buying the gas
+    pub fn capture_start<D: StateDB>(&mut self, state_db: &mut D) -> Result<(), statedb::Error> {
+        match self {
+            Self::Call(_) => Ok(()),
+            Self::Prestate(tracer) => tracer.capture_caller(state_db),
+        }
+    }
+
+    // runs the evm with the tracer
+    pub fn capture<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        match self {
+            Self::Call(tracer) => tracer.capture(f),
+            Self::Prestate(_) => f(),
+        }
+    }
+
+    // called after the evm exited, before applying the changes
+    pub fn capture_exit<D: StateDB>(
+        &mut self,
+        state_db: &mut D,
+        reason: &ExitReason,
+        output: &[u8],
+    ) -> Result<(), statedb::Error> {
+        match self {
+            Self::Call(tracer) => {
+                tracer.state.borrow_mut().top_exit = Some((reason.clone(), output.to_vec()));
+                Ok(())
+            }
+            Self::Prestate(tracer) => tracer.capture_pre(state_db),
+        }
+    }
+
+    pub fn finish<D: StateDB>(
+        self,
+        state_db: &mut D,
+        result: &ExecuteResult,
+    ) -> Result<TraceResult, statedb::Error> {
+        match self {
+            Self::Call(tracer) => Ok(TraceResult::Call(tracer.finish(result))),
+            Self::Prestate(tracer) => tracer.finish(state_db),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CallTracer {
+    state: Rc<RefCell<CallTracerState>>,
+}
+
+#[derive(Debug, Default)]
+struct CallTracerState {
+    only_top_call: bool,
+    // the precompiles exit without the `Exit` event
+    precompiles: BTreeSet<H160>,
+    stack: Vec<CallFrame>,
+    // the first call/create event of the tx belongs to the top frame
+    merge_top: bool,
+    top_exit: Option<(ExitReason, Vec<u8>)>,
+}
+
+impl CallTracer {
+    fn new(only_top_call: bool, ctx: &Context) -> Self {
+        let state = CallTracerState {
+            only_top_call,
+            precompiles: ctx.precompile.get_addresses().into_iter().collect(),
+            ..Default::default()
+        };
+        Self {
+            state: Rc::new(RefCell::new(state)),
+        }
+    }
+
+    fn capture<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        let mut listener = CallListener(self.state.clone());
+        let mut gas_listener = GasListener(self.state.clone());
+        evm_tracing::using(&mut listener, || gas_tracing::using(&mut gas_listener, f))
+    }
+
+    fn finish(self, result: &ExecuteResult) -> CallFrame {
+        let mut state = self.state.borrow_mut();
+        // the frames without an exit event are closed by their parents
+        while state.stack.len() > 1 {
+            state.pop();
+        }
+        let mut top = state.stack.pop().unwrap_or_default();
+        top.gas_used = result.used_gas;
+        top.output = None;
+        top.error = None;
+        if let Some((reason, output)) = &state.top_exit {
+            top.exit(reason, output);
+        }
+        top
+    }
+}
+
+impl CallTracerState {
+    fn push(&mut self, frame: CallFrame, precompile: bool) {
+        if self.merge_top {
+            self.merge_top = false;
+            // the top frame is already created by the transact event
+            if let [top] = self.stack.as_slice() {
+                if top.from == frame.from && top.to == frame.to {
+                    return;
+                }
+            }
+        }
+        if precompile {
+            if let Some(parent) = self.stack.last_mut() {
+                if !self.only_top_call {
+                    parent.calls.push(frame);
+                }
+            }
+            return;
+        }
+        self.stack.push(frame);
+    }
+
+    fn pop(&mut self) {
+        if self.stack.len() <= 1 {
+            return;
+        }
+        let frame = self.stack.pop().unwrap();
+        if !self.only_top_call {
+            self.stack.last_mut().unwrap().calls.push(frame);
+        }
+    }
+}
+
+struct CallListener(Rc<RefCell<CallTracerState>>);
+
+impl evm_tracing::EventListener for CallListener {
+    fn event(&mut self, event: evm_tracing::Event) {
+        use evm_tracing::Event;
+
+        let mut state = self.0.borrow_mut();
+        match event {
+            Event::TransactCall {
+                caller,
+                address,
+                value,
+                data,
+                gas_limit,
+            } => {
+                state.stack.push(CallFrame {
+                    ty: "CALL",
+                    from: caller.into(),
+                    to: Some(address.into()),
+                    value: Some(value.into()),
+                    gas: gas_limit.into(),
+                    input: data.to_vec().into(),
+                    ..Default::default()
+                });
+                state.merge_top = true;
+            }
+            Event::TransactCreate {
+                caller,
+                value,
+                init_code,
+                gas_limit,
+                address,
+            } => {
+                state.stack.push(CallFrame {
+                    ty: "CREATE",
+                    from: caller.into(),
+                    to: Some(address.into()),
+                    value: Some(value.into()),
+                    gas: gas_limit.into(),
+                    input: init_code.to_vec().into(),
+                    ..Default::default()
+                });
+                state.merge_top = true;
+            }
+            Event::TransactCreate2 {
+                caller,
+                value,
+                init_code,
+                gas_limit,
+                address,
+                ..
+            } => {
+                state.stack.push(CallFrame {
+                    ty: "CREATE2",
+                    from: caller.into(),
+                    to: Some(address.into()),
+                    value: Some(value.into()),
+                    gas: gas_limit.into(),
+                    input: init_code.to_vec().into(),
+                    ..Default::default()
+                });
+                state.merge_top = true;
+            }
+            Event::Call {
+                code_address,
+                transfer,
+                input,
+                target_gas,
+                is_static,
+                context,
+            } => {
+                // the code runs in the caller's context for DELEGATECALL and CALLCODE
+                let (ty, from) = if code_address != context.address {
+                    match transfer {
+                        Some(_) => ("CALLCODE", context.address),
+                        None => ("DELEGATECALL", context.address),
+                    }
+                } else if is_static {
+                    ("STATICCALL", context.caller)
+                } else {
+                    ("CALL", context.caller)
+                };
+                let frame = CallFrame {
+                    ty,
+                    from: from.into(),
+                    to: Some(code_address.into()),
+                    value: transfer.as_ref().map(|n| n.value.into()),
+                    gas: target_gas.unwrap_or_default().into(),
+                    input: input.to_vec().into(),
+                    ..Default::default()
+                };
+                let precompile = state.precompiles.contains(&code_address);
+                state.push(frame, precompile);
+            }
+            Event::Create {
+                caller,
+                address,
+                scheme,
+                value,
+                init_code,
+                target_gas,
+            } => {
+                let ty = match scheme {
+                    CreateScheme::Create2 { .. } => "CREATE2",
+                    _ => "CREATE",
+                };
+                let frame = CallFrame {
+                    ty,
+                    from: caller.into(),
+                    to: Some(address.into()),
+                    value: Some(value.into()),
+                    gas: target_gas.unwrap_or_default().into(),
+                    input: init_code.to_vec().into(),
+                    ..Default::default()
+                };
+                state.push(frame, false);
+            }
+            Event::Suicide {
+                address,
+                target,
+                balance,
+            } => {
+                if state.only_top_call {
+                    return;
+                }
+                if let Some(parent) = state.stack.last_mut() {
+                    parent.calls.push(CallFrame {
+                        ty: "SELFDESTRUCT",
+                        from: address.into(),
+                        to: Some(target.into()),
+                        value: Some(balance.into()),
+                        ..Default::default()
+                    });
+                }
+            }
+            Event::Exit {
+                reason,
+                return_value,
+            } => {
+                if state.stack.len() > 1 {
+                    state.stack.last_mut().unwrap().exit(reason, return_value);
+                    state.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+struct GasListener(Rc<RefCell<CallTracerState>>);
+
+impl gas_tracing::EventListener for GasListener {
+    fn event(&mut self, event: gas_tracing::Event) {
+        use gas_tracing::Event;
+
+        let snapshot = match event {
+            Event::RecordCost { snapshot, .. }
+            | Event::RecordRefund { snapshot, .. }
+            | Event::RecordStipend { snapshot, .. }
+            | Event::RecordDynamicCost { snapshot, .. }
+            | Event::RecordTransaction { snapshot, .. } => snapshot,
+        };
+        if let Some(snapshot) = snapshot {
+            let mut state = self.0.borrow_mut();
+            if let Some(frame) = state.stack.last_mut() {
+                frame.gas_used = (snapshot.used_gas + snapshot.memory_gas).into();
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PrestateTracer {
+    diff_mode: bool,
+    caller: SH160,
+    miner: SH160,
+    // the caller's account before buying the gas
+    caller_basic: Option<(SU256, u64)>,
+    touched: BTreeMap<SH160, Vec<SH256>>,
+    pre: BTreeMap<SH160, AccountState>,
+}
+
+impl PrestateTracer {
+    fn new(diff_mode: bool, caller: SH160, miner: SH160) -> Self {
+        Self {
+            diff_mode,
+            caller,
+            miner,
+            caller_basic: None,
+            touched: BTreeMap::new(),
+            pre: BTreeMap::new(),
+        }
+    }
+
+    fn capture_caller<D: StateDB>(&mut self, state_db: &mut D) -> Result<(), statedb::Error> {
+        let balance = state_db.get_balance(&self.caller)?;
+        let nonce = state_db.get_nonce(&self.caller)?;
+        self.caller_basic = Some((balance, nonce));
+        Ok(())
+    }
+
+    // the evm doesn't write to the state db until it exits, so all the touched accounts
+    // except the caller are still in their original state.
+    fn capture_pre<D: StateDB>(&mut self, state_db: &mut D) -> Result<(), statedb::Error> {
+        for item in state_db.export_access_list(None) {
+            self.touched
+                .entry(item.address)
+                .or_default()
+                .extend(item.storage_keys);
+        }
+        self.touched.entry(self.caller).or_default();
+        self.touched.entry(self.miner).or_default();
+
+        for (address, keys) in &self.touched {
+            let mut account = Self::read(state_db, address, keys)?;
+            if address == &self.caller {
+                if let Some((balance, nonce)) = self.caller_basic {
+                    account.balance = Some(balance);
+                    account.nonce = Some(nonce);
+                }
+            }
+            self.pre.insert(*address, account);
+        }
+        Ok(())
+    }
+
+    fn finish<D: StateDB>(self, state_db: &mut D) -> Result<TraceResult, statedb::Error> {
+        if !self.diff_mode {
+            return Ok(TraceResult::Prestate(self.pre));
+        }
+
+        let mut after = BTreeMap::new();
+        for (address, keys) in &self.touched {
+            after.insert(*address, Self::read(state_db, address, keys)?);
+        }
+        Ok(Self::diff(&self.pre, after))
+    }
+
+    fn diff(
+        pre_state: &BTreeMap<SH160, AccountState>,
+        post_state: BTreeMap<SH160, AccountState>,
+    ) -> TraceResult {
+        let mut pre = BTreeMap::new();
+        let mut post = BTreeMap::new();
+        for (address, after) in post_state {
+            let mut before = pre_state.get(&address).cloned().unwrap_or_default();
+            if before == after {
+                continue;
+            }
+
+            // only keeps the changed fields in post, and the changed slots in both
+            let mut changed = AccountState::default();
+            if before.balance != after.balance {
+                changed.balance = after.balance;
+            }
+            if before.nonce != after.nonce {
+                changed.nonce = after.nonce;
+            }
+            if before.code != after.code {
+                changed.code = after.code.clone();
+            }
+            before
+                .storage
+                .retain(|key, val| after.storage.get(key) != Some(val));
+            for (key, val) in &after.storage {
+                if before.storage.contains_key(key) {
+                    changed.storage.insert(*key, *val);
+                }
+            }
+
+            if !before.is_empty() {
+                pre.insert(address, before);
+            }
+            if !after.is_empty() {
+                post.insert(address, changed);
+            }
+        }
+        TraceResult::PrestateDiff { pre, post }
+    }
+
+    fn read<D: StateDB>(
+        state_db: &mut D,
+        address: &SH160,
+        keys: &[SH256],
+    ) -> Result<AccountState, statedb::Error> {
+        let code = state_db.get_code(address)?;
+        let mut storage = BTreeMap::new();
+        for key in keys {
+            storage.insert(*key, state_db.get_state(address, key)?);
+        }
+        Ok(AccountState {
+            balance: Some(state_db.get_balance(address)?),
+            nonce: Some(state_db.get_nonce(address)?),
+            code: (!code.is_empty()).then(|| code.as_ref().clone()),
+            storage,
+        })
+    }
+}
+
+// decodes the `Error(string)` of the revert data
+fn decode_revert_reason(output: &[u8]) -> Option<String> {
+    const SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+    if output.len() < 4 + 64 || output[..4] != SELECTOR {
+        return None;
+    }
+    let data = &output[4..];
+    let read_usize = |pos: usize| -> Option<usize> {
+        let word = data.get(pos..pos + 32)?;
+        if word[..24].iter().any(|n| *n != 0) {
+            return None;
+        }
+        let mut val = [0_u8; 8];
+        val.copy_from_slice(&word[24..]);
+        Some(u64::from_be_bytes(val) as usize)
+    };
+    let offset = read_usize(0)?;
+    let len = read_usize(offset)?;
+    let start = offset.checked_add(32)?;
+    let reason = data.get(start..start.checked_add(len)?)?;
+    Some(String::from_utf8_lossy(reason).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use eth_types::{H256, U256};
+    use evm::{ExitRevert, ExitSucceed, Transfer};
+
+    // abi.encodeWithSignature("Error(string)", reason)
+    fn revert_data(reason: &str) -> Vec<u8> {
+        let mut data = vec![0x08, 0xc3, 0x79, 0xa0];
+        let mut word = [0_u8; 32];
+        word[31] = 0x20;
+        data.extend_from_slice(&word);
+        word[31] = reason.len() as u8;
+        data.extend_from_slice(&word);
+        let mut payload = reason.as_bytes().to_vec();
+        payload.resize((reason.len() + 31) / 32 * 32, 0);
+        data.extend_from_slice(&payload);
+        data
+    }
+
+    fn addr(n: u8) -> H160 {
+        H160::from([n; 20])
+    }
+
+    #[test]
+    fn test_decode_revert_reason() {
+        assert_eq!(
+            decode_revert_reason(&revert_data("boom")),
+            Some("boom".into())
+        );
+        assert_eq!(decode_revert_reason(&revert_data("")), Some("".into()));
+        let long = "x".repeat(40);
+        assert_eq!(decode_revert_reason(&revert_data(&long)), Some(long));
+
+        // not an Error(string)
+        assert_eq!(decode_revert_reason(&[]), None);
+        let mut data = revert_data("boom");
+        data[0] = 0x4e;
+        assert_eq!(decode_revert_reason(&data), None);
+        // the length exceeds the data
+        let mut data = revert_data("boom");
+        data[4 + 63] = 0xff;
+        assert_eq!(decode_revert_reason(&data), None);
+        // the offset exceeds the data
+        let mut data = revert_data("boom");
+        data[4 + 31] = 0xff;
+        assert_eq!(decode_revert_reason(&data), None);
+    }
+
+    #[test]
+    fn test_call_delegatecall_revert() {
+        let state = Rc::new(RefCell::new(CallTracerState::default()));
+        let mut listener = CallListener(state.clone());
+        let (eoa, proxy, logic) = (addr(1), addr(2), addr(3));
+        let reason = revert_data("boom");
+        let reverted = ExitReason::Revert(ExitRevert::Reverted);
+
+        listener.event(evm_tracing::Event::TransactCall {
+            caller: eoa,
+            address: proxy,
+            value: U256::zero(),
+            data: &[1],
+            gas_limit: 100000,
+        });
+        // the top call is reported again by the evm
+        listener.event(evm_tracing::Event::Call {
+            code_address: proxy,
+            transfer: &Some(Transfer {
+                source: eoa,
+                target: proxy,
+                value: U256::zero(),
+            }),
+            input: &[1],
+            target_gas: Some(100000),
+            is_static: false,
+            context: &evm::Context {
+                address: proxy,
+                caller: eoa,
+                apparent_value: U256::zero(),
+            },
+        });
+        // the proxy delegates to the logic contract in its own context
+        listener.event(evm_tracing::Event::Call {
+            code_address: logic,
+            transfer: &None,
+            input: &[2],
+            target_gas: Some(50000),
+            is_static: false,
+            context: &evm::Context {
+                address: proxy,
+                caller: eoa,
+                apparent_value: U256::zero(),
+            },
+        });
+        listener.event(evm_tracing::Event::Exit {
+            reason: &reverted,
+            return_value: &reason,
+        });
+        listener.event(evm_tracing::Event::Exit {
+            reason: &reverted,
+            return_value: &reason,
+        });
+        state.borrow_mut().top_exit = Some((reverted.clone(), reason.clone()));
+
+        let tracer = CallTracer { state };
+        let result = ExecuteResult {
+            used_gas: SU64::from(30000_u64),
+            ..Default::default()
+        };
+        let top = tracer.finish(&result);
+        assert_eq!(top.ty, "CALL");
+        assert_eq!(top.from, eoa.into());
+        assert_eq!(top.to, Some(proxy.into()));
+        assert_eq!(top.error.as_deref(), Some("execution reverted"));
+        assert_eq!(top.revert_reason.as_deref(), Some("boom"));
+        assert_eq!(top.calls.len(), 1);
+
+        let inner = &top.calls[0];
+        assert_eq!(inner.ty, "DELEGATECALL");
+        assert_eq!(inner.from, proxy.into());
+        assert_eq!(inner.to, Some(logic.into()));
+        assert_eq!(inner.value, None);
+        assert_eq!(inner.input, vec![2_u8].into());
+        assert_eq!(inner.revert_reason.as_deref(), Some("boom"));
+        assert!(inner.calls.is_empty());
+    }
+
+    #[test]
+    fn test_staticcall_succeed() {
+        let state = Rc::new(RefCell::new(CallTracerState::default()));
+        let mut listener = CallListener(state.clone());
+        let (eoa, a, b) = (addr(1), addr(2), addr(3));
+        state.borrow_mut().stack.push(CallFrame {
+            ty: "CALL",
+            from: eoa.into(),
+            to: Some(a.into()),
+            ..Default::default()
+        });
+        listener.event(evm_tracing::Event::Call {
+            code_address: b,
+            transfer: &None,
+            input: &[],
+            target_gas: None,
+            is_static: true,
+            context: &evm::Context {
+                address: b,
+                caller: a,
+                apparent_value: U256::zero(),
+            },
+        });
+        listener.event(evm_tracing::Event::Exit {
+            reason: &ExitReason::Succeed(ExitSucceed::Returned),
+            return_value: &[7],
+        });
+        let state = state.borrow();
+        let inner = &state.stack[0].calls[0];
+        assert_eq!(inner.ty, "STATICCALL");
+        assert_eq!(inner.from, a.into());
+        assert_eq!(inner.output, Some(vec![7_u8].into()));
+        assert_eq!(inner.error, None);
+    }
+
+    #[test]
+    fn test_prestate_diff() {
+        let slot = |n: u8| -> SH256 { H256::from([n; 32]).into() };
+        let account = |balance: u64, nonce: u64| AccountState {
+            balance: Some(balance.into()),
+            nonce: Some(nonce),
+            ..Default::default()
+        };
+        let (caller, target, created, untouched) = (
+            addr(1).into(),
+            addr(2).into(),
+            addr(3).into(),
+            addr(4).into(),
+        );
+
+        let mut pre_state = BTreeMap::new();
+        pre_state.insert(caller, account(100, 1));
+        let mut target_pre = account(0, 1);
+        target_pre.code = Some(vec![0x60].into());
+        target_pre.storage.insert(slot(1), slot(1));
+        target_pre.storage.insert(slot(2), slot(2));
+        pre_state.insert(target, target_pre.clone());
+        pre_state.insert(untouched, account(5, 0));
+
+        let mut post_state = BTreeMap::new();
+        post_state.insert(caller, account(90, 2));
+        let mut target_post = target_pre.clone();
+        target_post.storage.insert(slot(2), slot(9));
+        post_state.insert(target, target_post);
+        post_state.insert(created, account(10, 1));
+        post_state.insert(untouched, account(5, 0));
+
+        let (pre, post) = match PrestateTracer::diff(&pre_state, post_state) {
+            TraceResult::PrestateDiff { pre, post } => (pre, post),
+            other => panic!("unexpected result: {:?}", other),
+        };
+
+        // the unchanged accounts are skipped, the created account has no pre state
+        assert_eq!(
+            pre.keys().cloned().collect::<Vec<_>>(),
+            vec![caller, target]
+        );
+        assert_eq!(
+            post.keys().cloned().collect::<Vec<_>>(),
+            vec![caller, target, created]
+        );
+        assert_eq!(pre[&caller], account(100, 1));
+        assert_eq!(post[&caller], account(90, 2));
+
+        // only the changed slot is kept, the unchanged fields are omitted in post
+        let mut expect_pre = target_pre;
+        expect_pre.storage.remove(&slot(1));
+        assert_eq!(pre[&target], expect_pre);
+        let mut expect_post = AccountState::default();
+        expect_post.storage.insert(slot(2), slot(9));
+        assert_eq!(post[&target], expect_post);
+
+        assert_eq!(post[&created], account(10, 1));
+    }
+}
@@ -5,7 +5,7 @@ use base::format::debug;
 use base::trace::Alive;
 use crypto::Aes128EncryptedMsg;
 use crypto::{Aes128Key, Sr25519PublicKey};
-use eth_types::{BundleRlp, PoolTx, PoolTxRlp, Signer};
+use eth_types::{PoolTx, PoolTxRlp, Signer};
 use jsonrpc::RpcEncrypt;
 use jsonrpc::{JsonrpcClient, JsonrpcWsClient, RpcError, WsClientError, WsSubscription};
 use serde::de::DeserializeOwned;
@@ -13,7 +13,7 @@ use serde::Serialize;
 use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 
-use crate::{GetTxRequest, SubscribeOpt};
+use crate::{BundleItem, GetTxRequest, SubscribeOpt};
 
 #[derive(Default)]
 pub struct AuthInfo {
@@ -71,7 +71,7 @@ impl Client {
         Ok(sub)
     }
 
-    pub fn subscribe_bundle(&self) -> Result<WsSubscription<BundleRlp>, RpcError> {
+    pub fn subscribe_bundle(&self) -> Result<WsSubscription<BundleItem>, RpcError> {
         let auth = self.check_auth()?;
         self.subscribe(
             "pool_subscribe",
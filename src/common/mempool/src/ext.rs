@@ -53,7 +53,8 @@ impl TxFetcherExtension for WithMempool {
                                 break;
                             }
                         };
-                        let bundle = match Bundle::from_rlp(&ctx.signer, bundle) {
+                        let dropping = bundle.dropping_tx_hashes.into_iter().collect();
+                        let bundle = match Bundle::from_rlp(&ctx.signer, bundle.bundle) {
                             Ok(n) => n,
                             Err(err) => {
                                 glog::error!("{:?}", err);
@@ -66,7 +67,7 @@ impl TxFetcherExtension for WithMempool {
                         }
                         glog::debug!(target: "txpool", "[{}] receive bundle {:?}, len: {}", name, hash, bundle.txs.len());
                         ctx.counter.add();
-                        if let Err(_) = ctx.sender.send(MempoolItem::Bundle(bundle)) {
+                        if let Err(_) = ctx.sender.send(MempoolItem::Bundle(bundle, dropping)) {
                             break;
                         }
                     }
@@ -1,6 +1,6 @@
 use std::prelude::v1::*;
 
-use eth_types::SU64;
+use eth_types::{BundleRlp, SH256, SU64};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -12,6 +12,16 @@ pub struct GetBundleRequest {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct GetTxRequest {}
 
+// the bundle with the txs which can be dropped from it, the field is omitted by the old nodes
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleItem {
+    #[serde(flatten)]
+    pub bundle: BundleRlp,
+    #[serde(default)]
+    pub dropping_tx_hashes: Vec<SH256>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SubscribeOpt {
     NewBundle,
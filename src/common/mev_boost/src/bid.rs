@@ -0,0 +1,228 @@
+use std::prelude::v1::*;
+
+use base::format::parse_ether;
+use base::serde::deserialize_ether;
+use eth_types::SU256;
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub struct BidContext<'a> {
+    pub tag: &'a str,
+    pub slot: u64,
+    // the builder's earning of the block before paying the proposer
+    pub profit: SU256,
+    // the block misses of the internal txs included in this block
+    pub internals: &'a [u64],
+    pub bundles: usize,
+    // the best bid from the other builders, if it's known
+    pub top_bid: Option<SU256>,
+}
+
+pub trait BidStrategy: std::fmt::Debug + Send + Sync {
+    // returns the value which will be paid to the proposer
+    fn bid(&self, ctx: &BidContext) -> SU256;
+
+    fn need_top_bid(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BidStrategyConfig {
+    Rule(TipsStrategy),
+    FixedPercent(FixedPercentBid),
+    BeatTopBid(BeatTopBid),
+}
+
+impl BidStrategyConfig {
+    pub fn build(&self) -> Arc<dyn BidStrategy> {
+        match self {
+            Self::Rule(n) => Arc::new(n.clone()),
+            Self::FixedPercent(n) => Arc::new(n.clone()),
+            Self::BeatTopBid(n) => Arc::new(n.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default, Eq, PartialEq)]
+pub struct TipsStrategy {
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub max: SU256,
+    pub bundle: Option<TipsStrategyRule>,
+    pub internal: Option<TipsStrategyRule>,
+    pub normal: Option<TipsStrategyRule>,
+}
+
+impl TipsStrategy {
+    pub fn get(&self, tag: &str, reward: SU256, internals: &[u64], bundles: usize) -> SU256 {
+        let bundle = if let Some(bundle) = &self.bundle {
+            if bundles > 0 {
+                bundle.start
+            } else {
+                SU256::zero()
+            }
+        } else {
+            SU256::zero()
+        };
+
+        let internal = if let Some(strategy) = &self.internal {
+            let mut total = SU256::zero();
+            if let Some(max_blk_miss) = internals.iter().max() {
+                total += strategy.start;
+                total += (SU256::from(internals.len() as u64) * strategy.num_increment)
+                    .min(strategy.max_num_increment);
+                total += (SU256::from(*max_blk_miss) * strategy.blocks_increment)
+                    .min(strategy.max_blocks_increment);
+            }
+            total
+        } else {
+            SU256::zero()
+        };
+
+        let normal = if let Some(strategy) = &self.normal {
+            strategy.start
+        } else {
+            SU256::zero()
+        };
+
+        let profit = reward + internal.max(bundle).max(normal).min(self.max);
+
+        glog::info!(
+            "[{}][profit={}] details: bundle({})={}, internal({:?})={}, normal={}, reward={}",
+            tag,
+            parse_ether(&profit, 18),
+            bundles,
+            parse_ether(&bundle, 18),
+            internals,
+            parse_ether(&internal, 18),
+            parse_ether(&normal, 18),
+            parse_ether(&reward, 18),
+        );
+        profit
+    }
+}
+
+impl BidStrategy for TipsStrategy {
+    fn bid(&self, ctx: &BidContext) -> SU256 {
+        self.get(ctx.tag, ctx.profit, ctx.internals, ctx.bundles)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default, Eq, PartialEq)]
+pub struct TipsStrategyRule {
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub start: SU256,
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub num_increment: SU256,
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub max_num_increment: SU256,
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub blocks_increment: SU256,
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub max_blocks_increment: SU256,
+}
+
+// pays a fixed percentage of the profit to the proposer.
+#[derive(Debug, Deserialize, Clone, Default, Eq, PartialEq)]
+pub struct FixedPercentBid {
+    pub percent: u64,
+}
+
+impl BidStrategy for FixedPercentBid {
+    fn bid(&self, ctx: &BidContext) -> SU256 {
+        let bid = ctx.profit * SU256::from(self.percent.min(100)) / SU256::from(100u64);
+        glog::info!(
+            "[{}][profit={}] fixed {}% of reward={}",
+            ctx.tag,
+            parse_ether(&bid, 18),
+            self.percent,
+            parse_ether(&ctx.profit, 18),
+        );
+        bid
+    }
+}
+
+// outbids the top bid by `epsilon`, but always keeps `margin`% of the profit.
+// bids the whole allowance if the top bid is unknown.
+#[derive(Debug, Deserialize, Clone, Default, Eq, PartialEq)]
+pub struct BeatTopBid {
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub epsilon: SU256,
+    pub margin: u64,
+}
+
+impl BidStrategy for BeatTopBid {
+    fn bid(&self, ctx: &BidContext) -> SU256 {
+        let limit = ctx.profit * SU256::from(100 - self.margin.min(100)) / SU256::from(100u64);
+        let bid = match &ctx.top_bid {
+            Some(top_bid) => (*top_bid + self.epsilon).min(limit),
+            None => limit,
+        };
+        glog::info!(
+            "[{}][profit={}] top_bid={:?}, limit={}, reward={}",
+            ctx.tag,
+            parse_ether(&bid, 18),
+            ctx.top_bid.map(|n| parse_ether(&n, 18)),
+            parse_ether(&limit, 18),
+            parse_ether(&ctx.profit, 18),
+        );
+        bid
+    }
+
+    fn need_top_bid(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ctx(profit: u64, top_bid: Option<u64>) -> BidContext<'static> {
+        BidContext {
+            tag: "test",
+            slot: 1,
+            profit: profit.into(),
+            internals: &[],
+            bundles: 0,
+            top_bid: top_bid.map(SU256::from),
+        }
+    }
+
+    #[test]
+    fn test_fixed_percent_bid() {
+        let strategy = FixedPercentBid { percent: 90 };
+        assert_eq!(strategy.bid(&ctx(1000, None)), SU256::from(900_u64));
+        // rounds down
+        assert_eq!(strategy.bid(&ctx(999, None)), SU256::from(899_u64));
+        assert_eq!(strategy.bid(&ctx(0, None)), SU256::zero());
+        assert!(!strategy.need_top_bid());
+
+        // never pays more than the profit
+        let strategy = FixedPercentBid { percent: 150 };
+        assert_eq!(strategy.bid(&ctx(1000, None)), SU256::from(1000_u64));
+    }
+
+    #[test]
+    fn test_beat_top_bid() {
+        let strategy = BeatTopBid {
+            epsilon: 10_u64.into(),
+            margin: 10,
+        };
+        assert!(strategy.need_top_bid());
+        // unknown top bid: bids the whole allowance
+        assert_eq!(strategy.bid(&ctx(1000, None)), SU256::from(900_u64));
+        // outbids the top bid by epsilon
+        assert_eq!(strategy.bid(&ctx(1000, Some(500))), SU256::from(510_u64));
+        // capped by the margin
+        assert_eq!(strategy.bid(&ctx(1000, Some(895))), SU256::from(900_u64));
+        assert_eq!(strategy.bid(&ctx(1000, Some(2000))), SU256::from(900_u64));
+
+        let strategy = BeatTopBid {
+            epsilon: 10_u64.into(),
+            margin: 200,
+        };
+        assert_eq!(strategy.bid(&ctx(1000, Some(500))), SU256::zero());
+    }
+}
@@ -5,6 +5,8 @@
 #[macro_use]
 extern crate sgxlib as std;
 
+mod bid;
+pub use bid::*;
 mod relay;
 pub use relay::*;
 mod types;
@@ -1,13 +1,13 @@
 use std::prelude::v1::*;
 
 use crate::{
-    BidTrace, BuilderSubmitBlockRequest, Config, Domain, Error, ExecutionPayload, Pubkey,
-    RelaySubmitter,
+    BidStrategy, BidStrategyConfig, BidTrace, BuilderSubmitBlockRequest, Config, Domain, Error,
+    ExecutionPayload, Pubkey, RelaySubmitter,
 };
 use base::format::parse_ether;
 use base::trace::Alive;
 use eth_client::BeaconSlot;
-use eth_types::{deserialize_u32, Block, HexBytes, SH160, SU256, SU64, SH256};
+use eth_types::{deserialize_u32, Block, HexBytes, SH160, SH256, SU256, SU64, U256};
 use net_http::{HttpClient, HttpMethod, HttpRequestBuilder};
 use serde::Deserialize;
 use std::collections::{btree_map::Entry, BTreeMap};
@@ -24,6 +24,18 @@ pub struct Relay {
 
     validators: Arc<Mutex<RelayValidatorMap>>,
     senders: Vec<(String, mpsc::SyncSender<Arc<BuilderSubmitBlockRequest>>)>,
+
+    client: Arc<HttpClient>,
+    endpoints: Vec<(String, String)>,
+    bid_strategies: BTreeMap<String, BidStrategyConfig>,
+}
+
+// the relays of a slot which share the same bid strategy, the block submitted to them
+// pays the same value to the proposer.
+#[derive(Clone, Debug)]
+pub struct RelayGroup {
+    pub relays: Vec<String>,
+    pub bid_strategy: Option<Arc<dyn BidStrategy>>,
 }
 
 impl Relay {
@@ -42,10 +54,12 @@ impl Relay {
         let validator_map = Arc::new(Mutex::new(RelayValidatorMap::new()));
         let mut senders = Vec::with_capacity(cfg.endpoints.len());
         let client = Arc::new(HttpClient::new());
+        let mut endpoints = Vec::with_capacity(cfg.endpoints.len());
         for (name, endpoint) in &cfg.endpoints {
             if name.starts_with("!") {
                 continue;
             }
+            endpoints.push((name.clone(), endpoint.clone()));
             base::thread::spawn(format!("relay-{}", name), {
                 let (sender, receiver) = mpsc::sync_channel(1);
                 let block_submitter = RelaySubmitter::new(
@@ -83,6 +97,9 @@ impl Relay {
             signer,
             validators: validator_map.clone(),
             senders,
+            client,
+            endpoints,
+            bid_strategies: cfg.bid_strategies,
         };
         relay
     }
@@ -120,8 +137,9 @@ impl Relay {
         client: &HttpClient,
         slot: u64,
         parent_hash: SH256,
-        pubkey: HexBytes,
-    ) -> Result<(), Error> {
+        pubkey: &HexBytes,
+        timeout: Duration,
+    ) -> Result<Option<SU256>, Error> {
         let uri = format!(
             "{}/eth/v1/builder/header/{}/{:?}/{}",
             endpoint, slot, parent_hash, pubkey
@@ -131,10 +149,91 @@ impl Relay {
 
         let mut req = HttpRequestBuilder::new(HttpMethod::Get, uri.parse().unwrap(), None);
         let response = client
-            .send(&mut req, Some(Duration::from_secs(1)))
-            .map_err(|err| Error::FetchValidatorFail(format!("[{}] {:?}", name, err)))?;
-        glog::info!("response: {}", String::from_utf8_lossy(&response.body));
-        Ok(())
+            .send(&mut req, Some(timeout))
+            .map_err(|err| Error::FetchBidFail(format!("[{}] {:?}", name, err)))?;
+        // 204: no bid for this slot yet
+        if response.body.is_empty() {
+            return Ok(None);
+        }
+        let bid: GetHeaderResponse = serde_json::from_slice(&response.body).map_err(|err| {
+            Error::FetchBidFail(format!(
+                "[{}]{}: {}",
+                name,
+                err,
+                String::from_utf8_lossy(&response.body)
+            ))
+        })?;
+        let value = U256::from_dec_str(&bid.data.message.value).map_err(|err| {
+            Error::FetchBidFail(format!("[{}] invalid value: {:?}", name, err))
+        })?;
+        Ok(Some(value.into()))
+    }
+
+    // relays are grouped by their bid strategy, a block is built for each group.
+    pub fn relay_groups(&self, vd: &ValidatorData) -> Vec<RelayGroup> {
+        group_relays(&self.bid_strategies, &vd.name)
+            .into_iter()
+            .map(|(strategy, relays)| RelayGroup {
+                relays,
+                bid_strategy: strategy.map(|strategy| strategy.build()),
+            })
+            .collect()
+    }
+
+    // fetches the best bid from the relays concurrently, the relays which don't respond
+    // before the deadline are ignored.
+    pub fn get_top_bid(
+        &self,
+        slot: u64,
+        parent_hash: SH256,
+        vd: &ValidatorData,
+        relays: &[String],
+    ) -> Option<SU256> {
+        let timeout = Duration::from_secs(1);
+        let deadline = Instant::now() + timeout;
+        let (sender, receiver) = mpsc::channel();
+        let mut pending = 0;
+        for (name, endpoint) in &self.endpoints {
+            if !relays.contains(name) {
+                continue;
+            }
+            pending += 1;
+            base::thread::spawn(format!("topbid-{}", name), {
+                let sender = sender.clone();
+                let client = self.client.clone();
+                let name = name.clone();
+                let endpoint = endpoint.clone();
+                let pubkey = vd.pub_key.clone();
+                move || {
+                    let result = Self::fetch_best_bid(
+                        &name,
+                        &endpoint,
+                        &client,
+                        slot,
+                        parent_hash,
+                        &pubkey,
+                        timeout,
+                    );
+                    let _ = sender.send(result);
+                }
+            });
+        }
+
+        let mut top_bid = None;
+        for _ in 0..pending {
+            let remain = deadline.saturating_duration_since(Instant::now());
+            match receiver.recv_timeout(remain) {
+                Ok(Ok(Some(bid))) => top_bid = Some(bid.max(top_bid.unwrap_or(SU256::zero()))),
+                Ok(Ok(None)) => {}
+                Ok(Err(err)) => glog::error!("fetch top bid fail: {:?}", err),
+                Err(_) => {
+                    glog::warn!("fetch top bid timeout, {} relays not responded", pending);
+                    break;
+                }
+            }
+            pending -= 1;
+        }
+        top_bid
     }
 
     fn fetch_validator(
@@ -186,11 +285,18 @@ impl Relay {
         }
     }
 
-    pub fn submit_block(&self, slot: u64, vd: &ValidatorData, blk: &Block, value: SU256) {
+    pub fn submit_block(
+        &self,
+        slot: u64,
+        vd: &ValidatorData,
+        relays: &[String],
+        blk: &Block,
+        value: SU256,
+    ) {
         let now = Instant::now();
         let req = Arc::new(self.build_request(slot, vd, value, blk));
         for (name, sender) in &self.senders {
-            if !vd.name.contains(name) {
+            if !relays.contains(name) {
                 continue;
             }
             let _ = sender.send(req.clone());
@@ -199,7 +305,7 @@ impl Relay {
         glog::info!(
             "[{}] submit result: to {:?}, recipient: {:?}, profit: {}, elapsed: {:?}",
             req.execution_payload.block_number,
-            vd.name,
+            relays,
             req.message.proposer_fee_recipient,
             parse_ether(&req.message.value.into(), 18),
             now.elapsed(),
@@ -212,6 +318,23 @@ impl Relay {
     }
 }
 
+// groups the relays which use the same bid strategy config, the relays without a
+// strategy fall into the `None` group.
+fn group_relays(
+    strategies: &BTreeMap<String, BidStrategyConfig>,
+    relays: &[String],
+) -> Vec<(Option<BidStrategyConfig>, Vec<String>)> {
+    let mut groups: Vec<(Option<BidStrategyConfig>, Vec<String>)> = Vec::new();
+    for relay in relays {
+        let strategy = strategies.get(relay);
+        match groups.iter_mut().find(|(n, _)| n.as_ref() == strategy) {
+            Some((_, group)) => group.push(relay.clone()),
+            None => groups.push((strategy.cloned(), vec![relay.clone()])),
+        }
+    }
+    groups
+}
+
 #[derive(Default, Clone, Debug)]
 pub struct RelayValidatorMap {
     data: BTreeMap<u64, ValidatorData>,
@@ -306,3 +429,50 @@ struct GetValidatorRelayResponseItemEntryMessage {
     timestamp: SU64,
     pubkey: String,
 }
+
+#[derive(Debug, Deserialize)]
+struct GetHeaderResponse {
+    data: GetHeaderResponseData,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetHeaderResponseData {
+    message: GetHeaderResponseMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetHeaderResponseMessage {
+    // decimal string in wei
+    value: String,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BeatTopBid, FixedPercentBid};
+
+    #[test]
+    fn test_group_relays() {
+        let fixed = BidStrategyConfig::FixedPercent(FixedPercentBid { percent: 90 });
+        let beat = BidStrategyConfig::BeatTopBid(BeatTopBid {
+            epsilon: SU256::from(1_u64),
+            margin: 5,
+        });
+        let mut strategies = BTreeMap::new();
+        strategies.insert("a".to_owned(), fixed.clone());
+        strategies.insert("b".to_owned(), beat.clone());
+        strategies.insert("c".to_owned(), fixed.clone());
+
+        let relays = ["a", "b", "c", "d"].map(|n| n.to_owned());
+        let groups = group_relays(&strategies, &relays);
+        assert_eq!(
+            groups,
+            vec![
+                (Some(fixed), vec!["a".to_owned(), "c".to_owned()]),
+                (Some(beat), vec!["b".to_owned()]),
+                (None, vec!["d".to_owned()]),
+            ]
+        );
+        assert_eq!(group_relays(&strategies, &[]), vec![]);
+    }
+}
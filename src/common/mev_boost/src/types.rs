@@ -2,6 +2,8 @@ use std::prelude::v1::*;
 
 use std::collections::BTreeMap;
 
+use crate::BidStrategyConfig;
+
 use blst::SecretKey;
 use eth_types::HexBytes;
 use eth_types::{serialize_u256, serialize_u64, Block};
@@ -17,6 +19,8 @@ pub struct Config {
     pub submit_time_millis: u64,
     pub timeout: Option<u64>,
     pub submit_timeout: Option<u64>,
+    #[serde(default)]
+    pub bid_strategies: BTreeMap<String, BidStrategyConfig>,
 }
 
 #[derive(Clone, Debug, Serialize, Eq, PartialEq)]
@@ -165,6 +169,7 @@ pub enum Error {
     NoValidatorInSlot { current: u64, next: u64 },
     InvalidValidatorPubkey,
     FetchValidatorFail(String),
+    FetchBidFail(String),
 }
 
 #[derive(Debug, Clone)]
@@ -5,13 +5,21 @@ use base::time::{now, SignedDuration};
 use eth_types::{Bundle, SH256, SU64};
 
 use serde::Serialize;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::{Arc, Mutex};
 
 pub struct BundlePool {
     list: Mutex<BundlePoolList>,
 }
 
+#[derive(Debug, Clone)]
+pub struct PoolBundle {
+    pub bundle: Arc<Bundle>,
+    // the txs which can be dropped from the bundle if they can't be included anymore(nonce too
+    // low or insufficient funds), instead of reverting the whole bundle.
+    pub dropping: BTreeSet<SH256>,
+}
+
 impl BundlePool {
     pub fn new() -> Self {
         Self {
@@ -26,12 +34,16 @@ impl BundlePool {
         self.list.lock().unwrap().uuid.len()
     }
 
-    pub fn add(&self, bundle: Bundle, dur: &SignedDuration) -> String {
+    pub fn add(&self, bundle: Bundle, dropping: BTreeSet<SH256>, dur: &SignedDuration) -> String {
         let bundle = Arc::new(bundle);
         let mut list = self.list.lock().unwrap();
-        let old = list.uuid.insert(bundle.uuid.clone(), bundle.clone());
+        let item = PoolBundle {
+            bundle: bundle.clone(),
+            dropping,
+        };
+        let old = list.uuid.insert(bundle.uuid.clone(), item);
         if let Some(old) = old {
-            let stat = list.get_stat(&old);
+            let stat = list.get_stat(&old.bundle);
             if stat.status != "submitted" {
                 stat.status = format!("replaced by {:?}", bundle.hash());
             }
@@ -53,7 +65,7 @@ impl BundlePool {
 
     pub fn get(&self, uuid: &str) -> Option<Arc<Bundle>> {
         let list = self.list.lock().unwrap();
-        list.uuid.get(uuid).map(|n| n.clone())
+        list.uuid.get(uuid).map(|n| n.bundle.clone())
     }
 
     pub fn set_history(&self, bundle: &Bundle, status: String) {
@@ -71,11 +83,12 @@ impl BundlePool {
         f(&list.history)
     }
 
-    pub fn list(&self, block_number: SU64, block_timestamp: u64) -> Vec<Arc<Bundle>> {
+    pub fn list(&self, block_number: SU64, block_timestamp: u64) -> Vec<PoolBundle> {
         let mut out = Vec::new();
         let mut list = self.list.lock().unwrap();
         let mut removed = Vec::new();
-        for (uuid, bundle) in &list.uuid {
+        for (uuid, item) in &list.uuid {
+            let bundle = &item.bundle;
             if block_number > bundle.block_number
                 || block_timestamp > bundle.max_timestamp.unwrap_or(u64::max_value())
             {
@@ -93,11 +106,11 @@ impl BundlePool {
             {
                 continue;
             }
-            out.push(bundle.clone());
+            out.push(item.clone());
         }
         for uuid in &removed {
             if let Some(item) = list.uuid.remove(uuid) {
-                list.get_stat(&item).status = "expired".into();
+                list.get_stat(&item.bundle).status = "expired".into();
             }
         }
         out
@@ -105,7 +118,7 @@ impl BundlePool {
 }
 
 pub struct BundlePoolList {
-    uuid: BTreeMap<String, Arc<Bundle>>,
+    uuid: BTreeMap<String, PoolBundle>,
     history: Vec<BundleStat>,
 }
 
@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 
 use eth_types::{Bundle, HexBytes, PoolTx, Signer, TransactionInner, SH160, SH256, SU256, SU64};
 use std::cmp::Reverse;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 
 #[derive(Debug)]
@@ -197,6 +197,8 @@ pub struct SendBundleRequest {
     pub min_timestamp: Option<u64>,
     pub max_timestamp: Option<u64>,
     pub reverting_tx_hashes: Option<Vec<SH256>>,
+    // txs which are allowed to be discarded if they are invalid, instead of failing the bundle
+    pub dropping_tx_hashes: Option<Vec<SH256>>,
     // String, UUID that can be used to cancel/replace this bundle
     pub uuid: Option<String>,
     // String, UUID that can be used to cancel/replace this bundle, has priority over `uuid` field
@@ -208,6 +210,19 @@ pub struct SendBundleRequest {
 }
 
 impl SendBundleRequest {
+    pub fn dropping_tx_hashes(&self, bundle: &Bundle) -> Result<BTreeSet<SH256>, String> {
+        let hashes = match &self.dropping_tx_hashes {
+            Some(hashes) => hashes,
+            None => return Ok(BTreeSet::new()),
+        };
+        for hash in hashes {
+            if !bundle.txs.iter().any(|tx| &tx.hash == hash) {
+                return Err(format!("dropping tx not found in bundle: {:?}", hash));
+            }
+        }
+        Ok(hashes.iter().cloned().collect())
+    }
+
     pub fn to_bundle(&self, signer: &Signer) -> Result<Bundle, String> {
         let reverting_tx_hashes = match &self.reverting_tx_hashes {
             Some(hashes) => hashes.clone(),
@@ -217,6 +232,11 @@ impl SendBundleRequest {
         if self.txs.len() == 0 {
             return Err("empty transactions".into());
         }
+        if let Some(refund_percent) = self.refund_percent {
+            if refund_percent > 99 {
+                return Err(format!("invalid refund percent: {}", refund_percent));
+            }
+        }
         for tx in &self.txs {
             let tx = TransactionInner::from_bytes(&tx).map_err(|err| format!("{:?}", err))?;
             let hash = tx.hash();